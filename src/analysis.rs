@@ -1,49 +1,93 @@
 use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
 
-use crate::lambda::recursive::LambdaSoup;
-use crate::utils::HeapObject;
+use crate::lambda::as_church_numeral;
+use crate::lambda::recursive::{
+    LambdaCollisionError, LambdaCollisionOk, LambdaParticle, LambdaSoup,
+};
+use crate::supercollider::Collider;
+use crate::utils::top_k_by;
 
 use lambda_calculus::Term;
+use rand::Rng;
+
+/// Differences between two soups' populations, as returned by `LambdaSoup::diff`. Every list
+/// is sorted by the magnitude of its counts, largest first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoupDiff {
+    /// Species only in the first soup, with their counts
+    pub lost: Vec<(Term, u32)>,
+
+    /// Species only in the second soup, with their counts
+    pub gained: Vec<(Term, u32)>,
+
+    /// Species in both soups whose count changed, with the change from the first to the second
+    pub changed: Vec<(Term, i64)>,
+}
 
 impl LambdaSoup {
-    // This is expensive, quadratic in the number of expressions. It can
-    // probably be written to be faster, but it's not a bottleneck right now.
+    /// Get the distinct species in the soup, keyed by `species_key` so alpha-equivalent
+    /// expressions are reported once. This reads the soup's species counts, not the
+    /// population.
     pub fn unique_expressions(&self) -> HashSet<Term> {
-        HashSet::<Term>::from_iter(self.lambda_expressions().cloned())
+        let keys = self.species_counts().keys();
+        keys.map(|k| k.as_term().clone()).collect()
     }
 
+    /// Number of distinct species in the soup, as in `unique_expressions`.
+    pub fn species_count(&self) -> usize {
+        self.species_counts().len()
+    }
+
+    /// Like `unique_expressions`, but in a deterministic order: by size, then by classic
+    /// notation.
+    pub fn unique_expressions_sorted(&self) -> Vec<Term> {
+        let mut exprs = self.unique_expressions().into_iter().collect::<Vec<_>>();
+        exprs.sort_by_cached_key(|t| (t.size(), t.to_string()));
+        exprs
+    }
+
+    /// Count the copies of each species, as given by `species_key`, so alpha-equivalent
+    /// expressions count as one species, matching `population_of`. Like `unique_expressions`,
+    /// this only visits the soup's species counts.
     pub fn expression_counts(&self) -> HashMap<Term, u32> {
-        let mut map = HashMap::<Term, u32>::new();
-        for expr in self.lambda_expressions().cloned() {
-            *map.entry(expr).or_default() += 1
-        }
-        map
+        let counts = self.species_counts().iter();
+        counts.map(|(k, c)| (k.as_term().clone(), *c)).collect()
     }
 
-    // The use of HeapObject is a code smell, refactor later
+    /// Get the `k` most common distinct expressions, most common first. Expressions with equal
+    /// counts are ordered by their De Bruijn form, so the same soup always gives the same list
+    /// whatever the iteration order of its counts.
     pub fn k_most_frequent_exprs(&self, k: usize) -> Vec<Term> {
         let mut map = HashMap::<&Term, u32>::new();
-        for x in self.lambda_expressions() {
-            *map.entry(x).or_default() += 1;
+        for (x, copies) in self.term_counts() {
+            *map.entry(x).or_default() += copies;
         }
+        let top = top_k_by(map, k, |(t, count)| (*count, Reverse(format!("{t:?}"))));
+        top.into_iter().map(|(t, _)| t.clone()).collect()
+    }
 
-        let mut heap = BinaryHeap::with_capacity(k + 1);
-        for (x, count) in map.into_iter() {
-            heap.push(Reverse(HeapObject::new(count, x)));
-            if heap.len() > k {
-                heap.pop();
-            }
+    /// Get the `k` largest distinct expressions with their sizes, largest first. Expressions of
+    /// equal size are ordered by their De Bruijn form.
+    pub fn largest_expressions(&self, k: usize) -> Vec<(Term, usize)> {
+        let sized = self.unique_expressions().into_iter().map(|t| {
+            let size = t.size();
+            (t, size)
+        });
+        top_k_by(sized, k, |(t, size)| (*size, Reverse(format!("{t:?}"))))
+    }
+
+    /// Count the expressions that reduce to each Church numeral value.
+    pub fn church_numeral_counts(&self) -> BTreeMap<u64, usize> {
+        let mut counts = BTreeMap::new();
+        for n in self.lambda_expressions().filter_map(as_church_numeral) {
+            *counts.entry(n).or_default() += 1;
         }
-        heap.into_sorted_vec()
-            .into_iter()
-            .map(|r| {
-                let tup = r.0.to_tuple();
-                tup.1.clone()
-            })
-            .collect()
+        counts
     }
 
+    /// Shannon entropy of the distribution of species, in base-10 units.
     pub fn population_entropy(&self) -> f32 {
         let mut entropy = 0.0;
         let n = self.len() as f32;
@@ -54,6 +98,41 @@ impl LambdaSoup {
         entropy
     }
 
+    /// Shannon entropy of the distribution of species, in units of `base`: bits for `2.0`,
+    /// nats for `e`. `population_entropy` is the same in base `10.0`.
+    pub fn population_entropy_base(&self, base: f32) -> f32 {
+        let entropy: f32 = self.species_proportions().map(|p| -p * p.ln()).sum();
+        entropy / base.ln()
+    }
+
+    /// Entropy divided by its maximum for the number of species present, the log of that
+    /// number, so that `1.0` is a perfectly even soup whatever its diversity. `0.0` for a soup
+    /// of at most one species.
+    pub fn normalized_entropy(&self) -> f32 {
+        let n_species = self.expression_counts().len();
+        if n_species <= 1 {
+            return 0.0;
+        }
+        self.population_entropy_base(n_species as f32)
+    }
+
+    /// Gini-Simpson index: the probability that two expressions drawn with replacement are of
+    /// different species. `0.0` for a soup of one species, and close to `1.0` for a soup of
+    /// many equally common ones.
+    pub fn simpson_diversity(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        1.0 - self.species_proportions().map(|p| p * p).sum::<f32>()
+    }
+
+    /// The fraction of the soup each species makes up, in no particular order.
+    fn species_proportions(&self) -> impl Iterator<Item = f32> {
+        let n = self.len() as f32;
+        let counts = self.expression_counts().into_iter();
+        counts.map(move |(_, c)| c as f32 / n)
+    }
+
     pub fn jacard_index(&self, other: &LambdaSoup) -> f32 {
         let selfcounts = self.expression_counts();
         let othercounts = other.expression_counts();
@@ -73,4 +152,455 @@ impl LambdaSoup {
         }
         (intersection as f32) / (union as f32)
     }
+
+    /// Compare this soup's species with `other`'s, e.g. before and after a perturbation.
+    /// Species are keyed as in `expression_counts`, and ties in magnitude are broken by De
+    /// Bruijn form so the output is deterministic.
+    pub fn diff(&self, other: &LambdaSoup) -> SoupDiff {
+        let before = self.expression_counts();
+        let mut after = other.expression_counts();
+
+        let mut lost = Vec::new();
+        let mut changed = Vec::new();
+        for (expr, count) in before {
+            match after.remove(&expr) {
+                None => lost.push((expr, count)),
+                Some(c) if c != count => changed.push((expr, c as i64 - count as i64)),
+                Some(_) => (),
+            }
+        }
+        let mut gained = after.into_iter().collect::<Vec<_>>();
+
+        lost.sort_by_cached_key(|(t, c)| (Reverse(*c), format!("{t:?}")));
+        gained.sort_by_cached_key(|(t, c)| (Reverse(*c), format!("{t:?}")));
+        changed.sort_by_cached_key(|(t, d)| (Reverse(d.unsigned_abs()), format!("{t:?}")));
+        SoupDiff {
+            lost,
+            gained,
+            changed,
+        }
+    }
+
+    /// Collide `k` random pairs of distinct expressions, drawn as in `react`, and return each
+    /// pair with its outcome. This is a dry run: the population, ages and collision count are
+    /// left alone, and only the soup's random stream advances. Returns nothing if the soup has
+    /// fewer than two expressions.
+    pub fn sample_reactions(
+        &mut self,
+        k: usize,
+    ) -> Vec<(Term, Term, Result<LambdaCollisionOk, LambdaCollisionError>)> {
+        let n_expr = self.len();
+        if n_expr < 2 {
+            return Vec::new();
+        }
+
+        (0..k)
+            .map(|_| {
+                let i = self.rng.gen_range(0..n_expr);
+                let mut j = self.rng.gen_range(0..n_expr - 1);
+                if j >= i {
+                    j += 1;
+                }
+                let (left, right) = (&self.expressions[i], &self.expressions[j]);
+                let outcome = self.collider.collide(left.clone(), right.clone());
+                (left.expr.clone(), right.expr.clone(), outcome)
+            })
+            .collect()
+    }
+
+    /// Simulate the soup for `n` collisions, recording the population of every species at
+    /// each poll. Polls are taken as in `simulate_and_poll`.
+    pub fn record_species_timeline(&mut self, n: usize, interval: usize) -> SpeciesTimeline {
+        let polls = self.simulate_and_poll(n, interval, false, |s| s.expression_counts());
+        SpeciesTimeline::from_counts(&polls)
+    }
+
+    /// Render the reaction network of the current population as a GraphViz DOT graph. Every
+    /// ordered pair of species is collided without touching the soup, and each product gets an
+    /// edge from both reactants (dashed for the right one) labelled by the rule that produced
+    /// it. Isomorphic expressions share a node. This is quadratic in the number of species.
+    pub fn export_reaction_graph_dot(&self) -> String {
+        const MAX_LABEL_LEN: usize = 40;
+
+        let mut counts = HashMap::<&LambdaParticle, usize>::new();
+        let mut species = Vec::new();
+        for p in self.expressions() {
+            let count = counts.entry(p).or_default();
+            if *count == 0 {
+                species.push(p);
+            }
+            *count += 1;
+        }
+
+        let mut nodes = Vec::<LambdaParticle>::new();
+        let mut ids = HashMap::<LambdaParticle, usize>::new();
+        let mut node_id = |p: &LambdaParticle| {
+            *ids.entry(p.clone()).or_insert_with(|| {
+                nodes.push(p.clone());
+                nodes.len() - 1
+            })
+        };
+        species.iter().for_each(|p| {
+            node_id(p);
+        });
+
+        let mut edges = BTreeSet::<(usize, usize, String, bool)>::new();
+        for (i, left) in species.iter().enumerate() {
+            for (j, right) in species.iter().enumerate() {
+                // A species can only react with itself if there are two copies of it
+                if i == j && counts[left] < 2 {
+                    continue;
+                }
+                let Ok(reaction) = self.collider.collide((*left).clone(), (*right).clone()) else {
+                    continue;
+                };
+                let (l, r) = (node_id(left), node_id(right));
+                for (i, product) in reaction.results.iter().enumerate() {
+                    let label = match reaction.rule_indices.get(i) {
+                        Some(rule) => rule.to_string(),
+                        None => String::from("test"),
+                    };
+                    let k = node_id(product);
+                    edges.insert((l, k, label.clone(), false));
+                    edges.insert((r, k, label, true));
+                }
+            }
+        }
+
+        let mut dot = String::from("digraph reactions {\n");
+        for (i, p) in nodes.iter().enumerate() {
+            let mut label = p.expr.to_string();
+            if label.chars().count() > MAX_LABEL_LEN {
+                label = label.chars().take(MAX_LABEL_LEN).collect::<String>() + "...";
+            }
+            let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+            let shape = if p.is_recursive() { "box" } else { "ellipse" };
+            writeln!(dot, "    n{i} [label=\"{label}\", shape={shape}];").unwrap();
+        }
+        for (src, dst, label, dashed) in edges {
+            let style = if dashed { ", style=dashed" } else { "" };
+            writeln!(dot, "    n{src} -> n{dst} [label=\"{label}\"{style}];").unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Population of every species at each poll of a run, for plotting population composition
+/// over time (e.g. as a Muller plot). Rows are species, in the order they first appeared, with
+/// species that appeared at the same poll ordered by De Bruijn form; columns are polls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpeciesTimeline {
+    species: Vec<Term>,
+    counts: Vec<Vec<u32>>,
+}
+
+impl SpeciesTimeline {
+    /// Build a timeline from per-poll `expression_counts`. Species absent from a poll count `0`
+    /// there.
+    pub fn from_counts(polls: &[HashMap<Term, u32>]) -> Self {
+        let mut rows = HashMap::<&Term, usize>::new();
+        let mut timeline = SpeciesTimeline {
+            species: Vec::new(),
+            counts: Vec::new(),
+        };
+        for (i, poll) in polls.iter().enumerate() {
+            let mut new = poll
+                .keys()
+                .filter(|t| !rows.contains_key(t))
+                .collect::<Vec<_>>();
+            new.sort_by_cached_key(|t| format!("{t:?}"));
+            for t in new {
+                rows.insert(t, timeline.species.len());
+                timeline.species.push(t.clone());
+                timeline.counts.push(vec![0; polls.len()]);
+            }
+            for (t, c) in poll {
+                timeline.counts[rows[t]][i] = *c;
+            }
+        }
+        timeline
+    }
+
+    /// Every species seen, in row order.
+    pub fn species(&self) -> &[Term] {
+        &self.species
+    }
+
+    /// Counts of each species (rows, as in `species`) at each poll (columns).
+    pub fn counts(&self) -> &[Vec<u32>] {
+        &self.counts
+    }
+
+    pub fn n_polls(&self) -> usize {
+        self.counts.first().map_or(0, Vec::len)
+    }
+
+    /// Render the timeline as CSV: a `term` column in classic notation, then one column per
+    /// poll, headed by its index.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("term");
+        for i in 0..self.n_polls() {
+            write!(csv, ",{i}").unwrap();
+        }
+        csv.push('\n');
+        for (t, row) in self.species.iter().zip(&self.counts) {
+            write!(csv, "\"{t}\"").unwrap();
+            for c in row {
+                write!(csv, ",{c}").unwrap();
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// Accumulates a histogram of reduction-step counts over the residues of many reactions, to
+/// see whether a soup's collisions are mostly cheap or dominated by a few expensive ones.
+/// Reductions that needed the reactor's `escalation` limits are also counted separately, as
+/// are reactions that ran out of steps (likely loops) and that outgrew the size limit.
+#[derive(Debug, Clone, Default)]
+pub struct ReductionHistogram {
+    counts: BTreeMap<usize, usize>,
+    escalated: usize,
+    step_limited: usize,
+    size_limited: usize,
+}
+
+impl ReductionHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the reduction counts of every rule applied in a successful collision.
+    pub fn record(&mut self, residue: &LambdaCollisionOk) {
+        for n in &residue.reductions {
+            *self.counts.entry(*n).or_default() += 1;
+        }
+        self.escalated += residue.escalated;
+    }
+
+    /// Record the outcome of `LambdaSoup::react`. Failed reactions only count towards
+    /// `step_limited` or `size_limited`, if they hit one of those limits.
+    pub fn record_reaction(&mut self, reaction: &Result<LambdaCollisionOk, LambdaCollisionError>) {
+        match reaction {
+            Ok(residue) => self.record(residue),
+            Err(LambdaCollisionError::ExceedsReductionLimit) => self.step_limited += 1,
+            Err(LambdaCollisionError::ExceedsSizeLimit) => self.size_limited += 1,
+            Err(_) => (),
+        }
+    }
+
+    /// Map from number of reduction steps to the number of reductions that took that many.
+    pub fn counts(&self) -> &BTreeMap<usize, usize> {
+        &self.counts
+    }
+
+    /// Total number of reductions recorded.
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// Number of the recorded reductions that only completed within the escalated limits.
+    pub fn escalated(&self) -> usize {
+        self.escalated
+    }
+
+    /// Number of recorded reactions that failed by running out of reduction steps.
+    pub fn step_limited(&self) -> usize {
+        self.step_limited
+    }
+
+    /// Number of recorded reactions that failed by outgrowing the size limit.
+    pub fn size_limited(&self) -> usize {
+        self.size_limited
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use lambda_calculus::{
+        combinators::{I, K, S},
+        parse,
+        term::Notation::Classic,
+        IntoChurchNum, Term,
+    };
+
+    use crate::config;
+    use crate::lambda::recursive::{
+        AlchemyCollider, LambdaCollisionError, LambdaParticle, LambdaSoup,
+    };
+    use crate::supercollider::Collider;
+
+    use super::{ReductionHistogram, SpeciesTimeline};
+
+    #[test]
+    fn alpha_variants_are_one_species() {
+        let a = parse(r"\x.\y.x y", Classic).unwrap();
+        let b = parse(r"\f.\v.f v", Classic).unwrap();
+        let mut soup = LambdaSoup::new();
+        soup.add_lambda_expressions([a.clone(), b]);
+
+        let counts = soup.expression_counts();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[&a], 2);
+        assert_eq!(soup.population_of(&a), 2);
+    }
+
+    #[test]
+    fn eta_variants_merge_with_use_eta() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            use_eta: true,
+            ..config::Reactor::new()
+        });
+        soup.add_lambda_expressions([parse(r"\f.\x.f x", Classic).unwrap(), I()]);
+        assert_eq!(soup.expression_counts().len(), 1);
+        assert_eq!(soup.population_of(&I()), 2);
+        assert_eq!(soup.population_of_any(&[I()]), [2]);
+    }
+
+    #[test]
+    fn alpha_variants_are_one_unique_expression() {
+        let mut soup = LambdaSoup::new();
+        soup.add_lambda_expressions([
+            parse(r"\x.x", Classic).unwrap(),
+            parse(r"\y.y", Classic).unwrap(),
+        ]);
+        assert_eq!(soup.unique_expressions().len(), 1);
+    }
+
+    #[test]
+    fn top_k_breaks_ties_deterministically() {
+        let numerals = (0..20).map(|n| n.into_church()).collect::<Vec<Term>>();
+        let soup_of = |terms: &[Term]| {
+            let mut soup = LambdaSoup::new();
+            soup.add_lambda_expressions(terms.iter().cloned());
+            soup.add_lambda_expressions([K(), K()]);
+            soup
+        };
+        let mut reversed = numerals.clone();
+        reversed.reverse();
+
+        let top = soup_of(&numerals).k_most_frequent_exprs(4);
+        assert_eq!(top[0], K());
+        assert_eq!(top.len(), 4);
+        for _ in 0..5 {
+            assert_eq!(soup_of(&numerals).k_most_frequent_exprs(4), top);
+            assert_eq!(soup_of(&reversed).k_most_frequent_exprs(4), top);
+        }
+    }
+
+    #[test]
+    fn diversity_metrics() {
+        let mut soup = LambdaSoup::new();
+        assert_eq!(soup.simpson_diversity(), 0.0);
+        soup.add_lambda_expressions([K(), I(), K(), I()]);
+        assert_eq!(soup.population_entropy_base(2.0), 1.0);
+        assert!((soup.population_entropy_base(10.0) - soup.population_entropy()).abs() < 1e-6);
+        assert_eq!(soup.normalized_entropy(), 1.0);
+        assert_eq!(soup.simpson_diversity(), 0.5);
+
+        soup.add_lambda_expressions([S(), S(), S()]);
+        assert!(soup.normalized_entropy() < 1.0);
+        soup.retain_terms(|t| *t == K());
+        assert_eq!(soup.normalized_entropy(), 0.0);
+        assert_eq!(soup.simpson_diversity(), 0.0);
+    }
+
+    #[test]
+    fn sample_reactions_leave_soup_alone() {
+        let mut soup = LambdaSoup::new();
+        soup.add_lambda_expressions([S(), K(), I()].into_iter().cycle().take(12));
+        let before = soup.expressions().cloned().collect::<Vec<_>>();
+
+        let outcomes = soup.sample_reactions(20);
+        assert_eq!(outcomes.len(), 20);
+        assert_eq!(soup.expressions().cloned().collect::<Vec<_>>(), before);
+        assert_eq!(soup.collisions(), 0);
+    }
+
+    #[test]
+    fn dry_collide_keeps_discarded_products() {
+        let collider = AlchemyCollider::from_config(&config::Reactor::new());
+        let mut soup = LambdaSoup::new();
+        soup.add_lambda_expressions([I(), I()]);
+        let i = soup.expressions().next().unwrap().clone();
+
+        let (residue, discards) = collider.dry_collide(i.clone(), i);
+        assert_eq!(residue.unwrap().results[0].expr, I());
+        assert_eq!(discards.len(), 1);
+        assert_eq!(discards[0].reason, LambdaCollisionError::IsIdentity);
+    }
+
+    #[test]
+    fn rule_isomorphs_can_be_discarded() {
+        // I R reduces to the rule R itself
+        let rule = r"\x.\y.x y";
+        let cfg = config::Reactor {
+            discard_copy_actions: config::CopyPolicy::Allow,
+            ..config::Reactor::default_with_rule(rule, config::ConfigSeed::new([0; 32]))
+        };
+        let r =
+            LambdaParticle::new(lambda_calculus::parse(rule, lambda_calculus::Classic).unwrap());
+        let i = LambdaParticle::new(I());
+
+        let collider = AlchemyCollider::from_config(&cfg);
+        assert_eq!(
+            collider.collide(i.clone(), r.clone()).unwrap().results[0],
+            r
+        );
+
+        let collider = AlchemyCollider::from_config(&config::Reactor {
+            discard_rule_isomorphs: true,
+            ..cfg
+        });
+        assert!(collider.to_config().discard_rule_isomorphs);
+        assert_eq!(collider.collide(i, r), Err(LambdaCollisionError::IsRule));
+    }
+
+    #[test]
+    fn escalation_rescues_slow_reductions() {
+        let (s, k) = (LambdaParticle::new(S()), LambdaParticle::new(K()));
+        let full = AlchemyCollider::from_config(&config::Reactor::new());
+        let n = full.collide(s.clone(), k.clone()).unwrap().reductions[0];
+
+        let cfg = config::Reactor {
+            reduction_cutoff: n - 1,
+            ..config::Reactor::new()
+        };
+        let strict = AlchemyCollider::from_config(&cfg);
+        let reaction = strict.collide(s.clone(), k.clone());
+        assert_eq!(reaction, Err(LambdaCollisionError::ExceedsReductionLimit));
+
+        let escalating = AlchemyCollider::from_config(&config::Reactor {
+            escalation: Some((10, 0)),
+            ..cfg
+        });
+        let residue = escalating.collide(s, k).unwrap();
+        assert_eq!((residue.reductions[0], residue.escalated), (n, 1));
+
+        let mut histogram = ReductionHistogram::new();
+        histogram.record(&residue);
+        assert_eq!((histogram.total(), histogram.escalated()), (1, 1));
+        histogram.record_reaction(&reaction);
+        assert_eq!((histogram.step_limited(), histogram.size_limited()), (1, 0));
+    }
+
+    #[test]
+    fn timeline_pads_missing_species() {
+        let polls = [
+            HashMap::from([(I(), 2)]),
+            HashMap::from([(K(), 1), (I(), 1)]),
+            HashMap::from([(K(), 2)]),
+        ];
+        let timeline = SpeciesTimeline::from_counts(&polls);
+        assert_eq!(timeline.species(), [I(), K()]);
+        assert_eq!(timeline.counts(), [vec![2, 1, 0], vec![0, 1, 2]]);
+        assert_eq!(
+            timeline.to_csv(),
+            format!("term,0,1,2\n\"{}\",2,1,0\n\"{}\",0,1,2\n", I(), K())
+        );
+    }
 }
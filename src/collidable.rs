@@ -0,0 +1,279 @@
+//! Adapters that build a `Collider` out of other colliders, so that a soup's chemistry can
+//! change over the course of a run without rebuilding the soup.
+//!
+//! `Collider::collide` takes `&self`, so the adapters keep their reaction count and random
+//! number generator in cells. Cloning an adapter clones that state too, which keeps cloned
+//! soups (and `Tape` snapshots) reproducible.
+
+use std::cell::{Cell, RefCell};
+
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::supercollider::{Collider, Particle};
+
+/// Applies `first` for `first_len` reactions, then `second`. With `cycling`, switches back to
+/// `first` after `second_len` reactions of `second`, and so on.
+///
+/// Every call to `collide` or `collide_all` counts as a reaction, whether or not it succeeds.
+#[derive(Debug, Clone)]
+pub struct SequenceCollider<A, B> {
+    first: A,
+    second: B,
+    first_len: usize,
+    second_len: Option<usize>,
+    n_reactions: Cell<usize>,
+}
+
+impl<A, B> SequenceCollider<A, B> {
+    /// Apply `first` for `first_len` reactions and `second` for every reaction after.
+    pub fn new(first: A, first_len: usize, second: B) -> Self {
+        SequenceCollider {
+            first,
+            second,
+            first_len,
+            second_len: None,
+            n_reactions: Cell::new(0),
+        }
+    }
+
+    /// Alternate between `first` for `first_len` reactions and `second` for `second_len`
+    /// reactions.
+    pub fn cycling(first: A, first_len: usize, second: B, second_len: usize) -> Self {
+        assert!(
+            first_len + second_len > 0,
+            "a cycle needs at least one reaction"
+        );
+        SequenceCollider {
+            second_len: Some(second_len),
+            ..Self::new(first, first_len, second)
+        }
+    }
+
+    /// Number of reactions seen so far.
+    pub fn reactions(&self) -> usize {
+        self.n_reactions.get()
+    }
+
+    /// Whether the next reaction will use `first`.
+    pub fn in_first(&self) -> bool {
+        let n = self.n_reactions.get();
+        match self.second_len {
+            None => n < self.first_len,
+            Some(second_len) => n % (self.first_len + second_len) < self.first_len,
+        }
+    }
+
+    /// Whether to use `first` for this reaction, counting it.
+    fn advance(&self) -> bool {
+        let in_first = self.in_first();
+        self.n_reactions.set(self.n_reactions.get() + 1);
+        in_first
+    }
+}
+
+impl<P, T, E, A, B> Collider<P, T, E> for SequenceCollider<A, B>
+where
+    P: Particle,
+    A: Collider<P, T, E>,
+    B: Collider<P, T, E>,
+{
+    fn collide(&self, left: P, right: P) -> Result<T, E> {
+        if self.advance() {
+            self.first.collide(left, right)
+        } else {
+            self.second.collide(left, right)
+        }
+    }
+
+    fn not_enough_expressions(&self) -> E {
+        if self.in_first() {
+            self.first.not_enough_expressions()
+        } else {
+            self.second.not_enough_expressions()
+        }
+    }
+
+    /// The arity of the collider the next reaction will use.
+    fn arity(&self) -> usize {
+        if self.in_first() {
+            self.first.arity()
+        } else {
+            self.second.arity()
+        }
+    }
+
+    fn collide_all(&self, reactants: Vec<P>) -> Result<T, E> {
+        if self.advance() {
+            self.first.collide_all(reactants)
+        } else {
+            self.second.collide_all(reactants)
+        }
+    }
+
+    /// Whether the collider the next reaction will use preserves the reactant.
+    fn preserves(&self, position: usize, reactant: &P) -> bool {
+        if self.in_first() {
+            self.first.preserves(position, reactant)
+        } else {
+            self.second.preserves(position, reactant)
+        }
+    }
+}
+
+/// Applies `left` with probability `p_left` and `right` otherwise, choosing independently for
+/// each reaction with its own generator.
+#[derive(Debug, Clone)]
+pub struct EitherCollider<A, B> {
+    left: A,
+    right: B,
+    p_left: f64,
+    rng: RefCell<ChaCha8Rng>,
+    /// The choice for the next reaction, drawn ahead so that `arity` can report it.
+    next_is_left: Cell<bool>,
+}
+
+impl<A, B> EitherCollider<A, B> {
+    /// Panics if `p_left` is not in `[0, 1]`.
+    pub fn new(left: A, right: B, p_left: f64, mut rng: ChaCha8Rng) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&p_left),
+            "p_left must be a probability, got {}",
+            p_left
+        );
+        let next_is_left = Cell::new(rng.gen_bool(p_left));
+        EitherCollider {
+            left,
+            right,
+            p_left,
+            rng: RefCell::new(rng),
+            next_is_left,
+        }
+    }
+
+    /// Whether the next reaction will use `left`.
+    pub fn next_is_left(&self) -> bool {
+        self.next_is_left.get()
+    }
+
+    /// Whether to use `left` for this reaction, drawing the choice for the next one.
+    fn advance(&self) -> bool {
+        let next = self.rng.borrow_mut().gen_bool(self.p_left);
+        self.next_is_left.replace(next)
+    }
+}
+
+impl<P, T, E, A, B> Collider<P, T, E> for EitherCollider<A, B>
+where
+    P: Particle,
+    A: Collider<P, T, E>,
+    B: Collider<P, T, E>,
+{
+    fn collide(&self, left: P, right: P) -> Result<T, E> {
+        if self.advance() {
+            self.left.collide(left, right)
+        } else {
+            self.right.collide(left, right)
+        }
+    }
+
+    fn not_enough_expressions(&self) -> E {
+        if self.next_is_left() {
+            self.left.not_enough_expressions()
+        } else {
+            self.right.not_enough_expressions()
+        }
+    }
+
+    /// The arity of the collider the next reaction will use.
+    fn arity(&self) -> usize {
+        if self.next_is_left() {
+            self.left.arity()
+        } else {
+            self.right.arity()
+        }
+    }
+
+    fn collide_all(&self, reactants: Vec<P>) -> Result<T, E> {
+        if self.advance() {
+            self.left.collide_all(reactants)
+        } else {
+            self.right.collide_all(reactants)
+        }
+    }
+
+    /// Whether the collider the next reaction will use preserves the reactant.
+    fn preserves(&self, position: usize, reactant: &P) -> bool {
+        if self.next_is_left() {
+            self.left.preserves(position, reactant)
+        } else {
+            self.right.preserves(position, reactant)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        combinators::{I, K},
+        Term,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::config;
+    use crate::lambda::recursive::{
+        AlchemyCollider, LambdaCollisionError, LambdaCollisionOk, LambdaParticle,
+    };
+    use crate::supercollider::Collider;
+
+    use super::{EitherCollider, SequenceCollider};
+
+    fn rule(rule: &str) -> AlchemyCollider {
+        AlchemyCollider::from_config(&config::Reactor::default_with_rule(
+            rule,
+            config::ConfigSeed::new([0; 32]),
+        ))
+    }
+
+    fn product(
+        collider: &impl Collider<LambdaParticle, LambdaCollisionOk, LambdaCollisionError>,
+    ) -> Term {
+        let result = collider
+            .collide(LambdaParticle::new(K()), LambdaParticle::new(I()))
+            .unwrap();
+        result.results[0].get_underlying_term().clone()
+    }
+
+    #[test]
+    fn sequence_switches_after_its_length() {
+        let (forward, backward) = (r"\x.\y.x y", r"\x.\y.y x");
+        // K I applied forward is \y.I, backward is I K = K
+        let k_i = product(&rule(forward));
+        assert_eq!(product(&rule(backward)), K());
+
+        let once = SequenceCollider::new(rule(forward), 2, rule(backward));
+        let products = (0..4).map(|_| product(&once)).collect::<Vec<_>>();
+        assert_eq!(products, [k_i.clone(), k_i.clone(), K(), K()]);
+
+        let cycle = SequenceCollider::cycling(rule(forward), 1, rule(backward), 2);
+        let products = (0..4).map(|_| product(&cycle)).collect::<Vec<_>>();
+        assert_eq!(products, [k_i.clone(), K(), K(), k_i]);
+        assert_eq!(cycle.reactions(), 4);
+    }
+
+    #[test]
+    fn either_is_reproducible() {
+        let either = EitherCollider::new(
+            rule(r"\x.\y.x y"),
+            rule(r"\x.\y.y x"),
+            0.5,
+            ChaCha8Rng::from_seed([3; 32]),
+        );
+        let copy = either.clone();
+        let first = (0..64).map(|_| product(&either)).collect::<Vec<_>>();
+        let second = (0..64).map(|_| product(&copy)).collect::<Vec<_>>();
+        assert_eq!(first, second);
+        assert!(first.contains(&K()) && first.iter().any(|t| *t != K()));
+    }
+}
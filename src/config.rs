@@ -1,4 +1,7 @@
-use rand::{thread_rng, Rng};
+use lambda_calculus::reduction::Order;
+use rand::distributions::WeightedIndex;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use serde::{Deserialize, Serialize};
 
@@ -33,29 +36,94 @@ pub struct Config {
 /// Configuration for the reactor
 #[warn(missing_docs)]
 #[derive(Serialize, Deserialize, Debug)]
+// The derives become inherent functions, so the trait impls below can check `rule_orders`
+// against `rules` after deserializing
+#[serde(remote = "Self")]
 pub struct Reactor {
     /// Set of reaction rules. Each rule must always be a lambda expressions
     /// with two arguments. Default: `["\x.\y.x y"]`.
     pub rules: Vec<String>,
 
-    /// When set, remove all results that are structurally isomorphic to parents.
-    /// Default: `true`.
-    pub discard_copy_actions: bool,
+    /// The strategy rule compositions are reduced with, unless `rule_orders` overrides it.
+    /// Default: `HAP`.
+    #[serde(default)]
+    pub reduction_order: ReductionOrder,
+
+    /// Per-rule overrides of `reduction_order`, index-aligned with `rules`, for chemistries
+    /// whose rules only converge under different strategies. `None`, or a missing entry, uses
+    /// `reduction_order`. Must not be longer than `rules`, which is checked when the config is
+    /// deserialized. Default: none.
+    #[serde(default)]
+    pub rule_orders: Vec<Option<ReductionOrder>>,
+
+    /// Which results that are structurally isomorphic to parents get removed. For backwards
+    /// compatibility, `true` is read as `DiscardEither` and `false` as `Allow`.
+    /// Default: `DiscardEither`.
+    #[serde(deserialize_with = "deserialize_copy_policy")]
+    pub discard_copy_actions: CopyPolicy,
 
     /// When set, remove all results that are structurally isomorphic to the identity function:
     /// `\x.x`. Default: `true`.
     pub discard_identity: bool,
 
+    /// When set, remove all results that are isomorphic to one of the `rules`, so that the
+    /// reaction operator cannot leak into the population and start reacting. With `use_eta`,
+    /// eta-equivalent results are removed too. Default: `false`.
+    #[serde(default)]
+    pub discard_rule_isomorphs: bool,
+
+    /// Catalysts: lambda expressions that are never consumed as the left reactant. When one
+    /// reacts on the left, it goes back into the soup whether or not the reaction succeeds,
+    /// while the right reactant is consumed if the reaction succeeds, even without
+    /// `discard_parents`. With `use_eta`, eta-equivalent expressions are catalysts too.
+    /// Default: none.
+    #[serde(default)]
+    pub catalysts: Vec<String>,
+
     /// When set, remove all expressions that contain free variables. Default: `true`.
     pub discard_free_variable_expressions: bool,
 
+    /// When set, try the rules in order and keep only the product of the first one whose result
+    /// survives the discard filters (and reduction limits), instead of one product per rule.
+    /// The reaction fails only if every rule does. Default: `false`.
+    #[serde(default)]
+    pub first_nontrivial_rule: bool,
+
+    /// When set, treat eta-equivalent expressions (`\x. f x` and `f`) as the same species,
+    /// both in the discard checks and when counting species. This eta-normalizes every
+    /// expression compared or counted, which is up to quadratic in its size. Default: `false`.
+    #[serde(default)]
+    pub use_eta: bool,
+
+    /// Number of copies of a function added to the soup when it passes a test expression, which
+    /// sets the strength of selection in the magic-test experiments. Default: `100`.
+    #[serde(default = "default_test_reward")]
+    pub test_reward: usize,
+
     /// When set, remove the parents from the soup instead of returning them. Default: `true`.
     pub discard_parents: bool,
 
+    /// The most distinct species the soup may hold. Whenever a reaction pushes the soup past
+    /// this, every copy of the least frequent species is removed until it fits, which keeps
+    /// analysis maps bounded on very long runs. This is a lossy pressure that changes the
+    /// dynamics and can shrink the population. When set to `None`, diversity is unbounded.
+    /// Default: `None`.
+    #[serde(default)]
+    pub max_species: Option<usize>,
+
+    /// The most expressions the soup may hold. Whenever a reaction pushes the soup past this,
+    /// randomly chosen expressions are removed until it fits. This is a safety valve against
+    /// runaway growth, e.g. from test rewards with `maintain_constant_population_size` unset;
+    /// with that option set, reactions never grow the soup and the cap only trims a soup that
+    /// was perturbed past it. When set to `None`, the population is unbounded. Default: `None`.
+    #[serde(default)]
+    pub max_population: Option<usize>,
+
     /// When set maintain a constant population size after each reaction. If there are more
     /// elements than the population originally started with, then remove elements randomly from
     /// the soup until the original population remains. If there are fewer elements after a
-    /// reaction, then do nothing. This behavior may change. Default: `true`.
+    /// reaction, then do nothing. This behavior may change. To bound a growing soup instead,
+    /// see `max_population`. Default: `true`.
     pub maintain_constant_population_size: bool,
 
     ///  The number of reductions allowed before AlChemy gives up and fails the reaction. Default:
@@ -65,11 +133,146 @@ pub struct Reactor {
     /// The largest size of any expression during a reduction step. Defaults to `1024`.
     pub size_cutoff: usize,
 
+    /// Extra reduction steps and extra expression size, in that order, for a second attempt at
+    /// a reduction that exceeds `reduction_cutoff`. The reaction is only rejected if it also
+    /// fails with the raised limits. When set to `None`, there is no second attempt.
+    /// Default: `None`.
+    #[serde(default)]
+    pub escalation: Option<(usize, usize)>,
+
+    /// The deepest nesting of any expression during a reduction step, independent of its size.
+    /// When set to `None`, only `size_cutoff` bounds the expression. Default: `None`.
+    #[serde(default)]
+    pub depth_cutoff: Option<usize>,
+
+    /// Wall-clock time, in milliseconds, a single reduction may take before AlChemy gives up
+    /// and fails the reaction. When set to `None`, reductions are only bounded by
+    /// `reduction_cutoff` and `size_cutoff`. Default: `None`.
+    #[serde(default)]
+    pub reduction_timeout_ms: Option<u64>,
+
     /// The seed for the reactor. If set to `None`, then a seed is chosen
     /// randomly. Default: `None`
     pub seed: ConfigSeed,
 }
 
+/// A reduction strategy, mirroring `lambda_calculus::reduction::Order`, which has no serde
+/// support. Serialized by the same upper-case names. Only the strategies that reduce to full
+/// normal form are offered: call-by-name, call-by-value and head spine stop at weak or head
+/// normal forms, which would split one species into several unreduced products.
+#[warn(missing_docs)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ReductionOrder {
+    /// Normal order
+    Nor,
+
+    /// Applicative order
+    App,
+
+    /// Hybrid normal order
+    Hno,
+
+    /// Hybrid applicative order
+    #[default]
+    Hap,
+}
+
+impl From<ReductionOrder> for Order {
+    fn from(order: ReductionOrder) -> Self {
+        match order {
+            ReductionOrder::Nor => Order::NOR,
+            ReductionOrder::App => Order::APP,
+            ReductionOrder::Hno => Order::HNO,
+            ReductionOrder::Hap => Order::HAP,
+        }
+    }
+}
+
+impl Serialize for Reactor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        Reactor::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Reactor {
+    /// Deserialize a reactor, rejecting more `rule_orders` than `rules`.
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let cfg = Reactor::deserialize(deserializer)?;
+        if cfg.rule_orders.len() > cfg.rules.len() {
+            return Err(serde::de::Error::custom(format!(
+                "{} rule orders given for {} rules",
+                cfg.rule_orders.len(),
+                cfg.rules.len()
+            )));
+        }
+        Ok(cfg)
+    }
+}
+
+/// Policy for reaction results that are structurally isomorphic to one of the reactants.
+#[warn(missing_docs)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyPolicy {
+    /// Keep all copy actions
+    Allow,
+
+    /// Discard results isomorphic to either reactant
+    DiscardEither,
+
+    /// Discard results isomorphic to the left reactant only
+    DiscardLeft,
+
+    /// Discard results isomorphic to the right reactant only
+    DiscardRight,
+}
+
+impl CopyPolicy {
+    /// Whether a result that copies the left and/or right reactant should be discarded.
+    pub fn discards(&self, copies_left: bool, copies_right: bool) -> bool {
+        match self {
+            CopyPolicy::Allow => false,
+            CopyPolicy::DiscardEither => copies_left || copies_right,
+            CopyPolicy::DiscardLeft => copies_left,
+            CopyPolicy::DiscardRight => copies_right,
+        }
+    }
+}
+
+impl From<bool> for CopyPolicy {
+    fn from(discard: bool) -> Self {
+        if discard {
+            CopyPolicy::DiscardEither
+        } else {
+            CopyPolicy::Allow
+        }
+    }
+}
+
+/// Accept either a `CopyPolicy` variant or the legacy boolean flag.
+fn deserialize_copy_policy<'de, D>(deserializer: D) -> Result<CopyPolicy, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Flag(bool),
+        Policy(CopyPolicy),
+    }
+
+    Ok(match Repr::deserialize(deserializer)? {
+        Repr::Flag(discard) => discard.into(),
+        Repr::Policy(policy) => policy,
+    })
+}
+
 /// Configuration for the generators
 #[warn(missing_docs)]
 #[derive(Serialize, Deserialize, Debug)]
@@ -93,8 +296,10 @@ pub struct BTreeGen {
     /// randomly. Default: `None`
     pub seed: ConfigSeed,
 
-    /// Number of nodes in the binary tree
-    pub size: u32,
+    /// Number of nodes in the binary tree, either fixed or drawn from a distribution for every
+    /// generated expression. A plain number is read as `Fixed`. Default: `Fixed(20)`.
+    #[serde(deserialize_with = "deserialize_size_spec")]
+    pub size: SizeSpec,
 
     /// Probability that a leaf vertex is a free variable
     pub freevar_generation_probability: f64,
@@ -105,6 +310,73 @@ pub struct BTreeGen {
     /// Standardization scheme. Defaults to prefix standardization (this is different from the
     /// paper!)
     pub standardization: Standardization,
+
+    /// When set, reject and regenerate terms nested deeper than this, as measured by
+    /// `lambda::term_depth` after standardization. Trees of the same size vary widely in depth,
+    /// so this controls the other axis of complexity. Default: `None`.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+}
+
+/// Distribution of binary tree sizes for the BTree generator
+#[warn(missing_docs)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SizeSpec {
+    /// Always use this size
+    Fixed(u32),
+
+    /// Pick a size uniformly from the inclusive range `(min, max)`
+    Uniform(u32, u32),
+
+    /// Pick a size with probability proportional to its weight
+    Weighted(Vec<(u32, f64)>),
+}
+
+impl SizeSpec {
+    /// Check that every size this spec can draw is positive and that it can be sampled at all:
+    /// a uniform range must not be empty, and weights must be non-empty, finite, non-negative
+    /// and not all zero.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            SizeSpec::Fixed(0) | SizeSpec::Uniform(0, _) => {
+                Err("btree size must be positive".into())
+            }
+            SizeSpec::Fixed(_) => Ok(()),
+            SizeSpec::Uniform(min, max) if min > max => Err(format!(
+                "btree size range is empty: min {} is greater than max {}",
+                min, max
+            )),
+            SizeSpec::Uniform(_, _) => Ok(()),
+            SizeSpec::Weighted(weights) => {
+                if weights.iter().any(|(n, w)| *n == 0 && *w > 0.0) {
+                    return Err("btree size must be positive".into());
+                }
+                WeightedIndex::new(weights.iter().map(|(_, w)| *w))
+                    .map(|_| ())
+                    .map_err(|e| format!("invalid btree size weights: {}", e))
+            }
+        }
+    }
+}
+
+/// Accept either a `SizeSpec` or a plain number, which is read as `SizeSpec::Fixed`.
+fn deserialize_size_spec<'de, D>(deserializer: D) -> Result<SizeSpec, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Size(u32),
+        Spec(SizeSpec),
+    }
+
+    let spec = match Repr::deserialize(deserializer)? {
+        Repr::Size(n) => SizeSpec::Fixed(n),
+        Repr::Spec(spec) => spec,
+    };
+    spec.validate().map_err(serde::de::Error::custom)?;
+    Ok(spec)
 }
 
 #[warn(missing_docs)]
@@ -134,6 +406,33 @@ pub struct FontanaGen {
 
     /// Size of the free variable palette
     pub n_max_free_vars: u32,
+
+    /// When set, close every generated term by prefix standardization: wrap it in
+    /// abstractions until it has no free variables. Default: `true`.
+    #[serde(default = "default_close_terms")]
+    pub close_terms: bool,
+
+    /// When set, rejection-sample so that generated terms spread evenly over sizes instead of
+    /// following the generator's natural distribution. Default: `false`.
+    #[serde(default)]
+    pub flatten_sizes: bool,
+
+    /// With `flatten_sizes`, the number of terms of each size to accept before that size is
+    /// rejected. Default: `100`.
+    #[serde(default = "default_target_per_size")]
+    pub target_per_size: usize,
+}
+
+fn default_test_reward() -> usize {
+    100
+}
+
+fn default_close_terms() -> bool {
+    true
+}
+
+fn default_target_per_size() -> usize {
+    100
 }
 
 impl GenConfig for FontanaGen {
@@ -146,6 +445,9 @@ impl GenConfig for FontanaGen {
             application_prob_range: (0.3, 0.5),
             abstraction_prob_range: (0.5, 0.3),
             free_variable_probability: 0.0,
+            close_terms: true,
+            flatten_sizes: false,
+            target_per_size: default_target_per_size(),
         }
     }
 }
@@ -155,17 +457,58 @@ impl Reactor {
     pub fn new() -> Self {
         Reactor {
             rules: vec![String::from("\\x.\\y.x y")],
+            reduction_order: ReductionOrder::default(),
+            rule_orders: Vec::new(),
 
-            discard_copy_actions: true,
+            discard_copy_actions: CopyPolicy::DiscardEither,
             discard_identity: true,
+            discard_rule_isomorphs: false,
+            catalysts: Vec::new(),
             discard_free_variable_expressions: true,
+            first_nontrivial_rule: false,
+            use_eta: false,
+            test_reward: default_test_reward(),
             maintain_constant_population_size: true,
             discard_parents: false,
+            max_species: None,
+            max_population: None,
             reduction_cutoff: 500,
             size_cutoff: 500,
+            depth_cutoff: None,
+            escalation: None,
+            reduction_timeout_ms: None,
             seed: ConfigSeed(None),
         }
     }
+
+    /// Produce the reactor used throughout the experiments: a single `rule`, copy actions and
+    /// identity results allowed, free-variable results discarded, parents returned, a constant
+    /// population, a reduction cutoff of `8000` and a size cutoff of `1000`.
+    pub fn default_with_rule(rule: &str, seed: ConfigSeed) -> Self {
+        Reactor {
+            rules: vec![String::from(rule)],
+            reduction_order: ReductionOrder::default(),
+            rule_orders: Vec::new(),
+            discard_copy_actions: CopyPolicy::Allow,
+            discard_identity: false,
+            discard_rule_isomorphs: false,
+            catalysts: Vec::new(),
+            discard_free_variable_expressions: true,
+            first_nontrivial_rule: false,
+            use_eta: false,
+            test_reward: default_test_reward(),
+            maintain_constant_population_size: true,
+            discard_parents: false,
+            max_species: None,
+            max_population: None,
+            reduction_cutoff: 8000,
+            size_cutoff: 1000,
+            depth_cutoff: None,
+            escalation: None,
+            reduction_timeout_ms: None,
+            seed,
+        }
+    }
 }
 
 // TODO: Eventually, all config objects will use `default` instead of `new`. For now, this just
@@ -180,10 +523,11 @@ impl GenConfig for BTreeGen {
     /// Produce a new `BTreeGenConfig` struct with default values.
     fn new() -> Self {
         BTreeGen {
-            size: 20,
+            size: SizeSpec::Fixed(20),
             freevar_generation_probability: 0.2,
             standardization: Standardization::Prefix,
             n_max_free_vars: 6,
+            max_depth: None,
             seed: ConfigSeed(None),
         }
     }
@@ -258,6 +602,17 @@ impl ConfigSeed {
     pub fn blank() -> Self {
         ConfigSeed(None)
     }
+
+    /// Derive an independent seed for task `index` of a parallel run. The result is the first
+    /// 32 bytes of the ChaCha8 stream numbered `index` under this seed, so distinct indices give
+    /// unrelated seeds for any `u64`. A blank seed derives a blank seed.
+    pub fn derive(&self, index: u64) -> ConfigSeed {
+        ConfigSeed(self.0.map(|seed| {
+            let mut rng = ChaCha8Rng::from_seed(seed);
+            rng.set_stream(index);
+            rng.gen()
+        }))
+    }
 }
 
 /// Manually serialize [u8; 32] to a hex string
@@ -282,12 +637,142 @@ impl<'de> Deserialize<'de> for ConfigSeed {
     where
         D: serde::Deserializer<'de>,
     {
-        let seed_string: Option<&str> = serde::de::Deserialize::deserialize(deserializer)?;
+        // Owned, so that seeds can also be read from streams, which cannot lend out strings
+        let seed_string: Option<String> = serde::de::Deserialize::deserialize(deserializer)?;
         Ok(if let Some(s) = seed_string {
-            let hexvec = decode_hex(s).unwrap();
+            let hexvec = decode_hex(&s).unwrap();
             ConfigSeed::new(hexvec.try_into().unwrap())
         } else {
             ConfigSeed::blank()
         })
     }
 }
+
+/// Named reactors for the chemistries used in the experiments, so that runs and notebooks can
+/// share a known-good configuration instead of restating its flags.
+pub mod presets {
+    use super::{ConfigSeed, Reactor};
+
+    /// The names accepted by `by_name`.
+    pub const NAMES: &[&str] = &["standard", "discard_identity"];
+
+    /// The reactor used throughout the experiments: `default_with_rule` with the single rule
+    /// `\x.\y.x y`. Identity results are kept.
+    pub fn standard_reactor(seed: ConfigSeed) -> Reactor {
+        Reactor::default_with_rule(r"\x.\y.x y", seed)
+    }
+
+    /// `standard_reactor`, but collisions that produce the identity are discarded.
+    pub fn discard_identity(seed: ConfigSeed) -> Reactor {
+        Reactor {
+            discard_identity: true,
+            ..standard_reactor(seed)
+        }
+    }
+
+    /// Look up a preset by one of the `NAMES`.
+    pub fn by_name(name: &str, seed: ConfigSeed) -> Option<Reactor> {
+        match name {
+            "standard" => Some(standard_reactor(seed)),
+            "discard_identity" => Some(discard_identity(seed)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{
+        BTreeGen, Config, ConfigSeed, CopyPolicy, GenConfig, Generator, Reactor, ReductionOrder,
+        SizeSpec,
+    };
+
+    #[test]
+    fn legacy_config_fields_parse() {
+        let cfg = Config::from_config_str(include_str!("../default/config.json"));
+        assert_eq!(
+            cfg.reactor_config.discard_copy_actions,
+            CopyPolicy::DiscardEither
+        );
+        let Generator::BTree(gen) = cfg.generator_config else {
+            panic!("default config uses the btree generator")
+        };
+        assert_eq!(gen.size, SizeSpec::Fixed(20));
+    }
+
+    #[test]
+    fn derived_seeds_are_distinct() {
+        let base = ConfigSeed::new([0; 32]);
+        let seeds = (0..1000)
+            .map(|i| base.derive(i).get())
+            .collect::<HashSet<_>>();
+        assert_eq!(seeds.len(), 1000);
+        assert_eq!(base.derive(256).get(), base.derive(256).get());
+        assert!(ConfigSeed::blank().derive(0).seed().is_none());
+    }
+
+    #[test]
+    fn config_round_trips() {
+        let mut cfg = Config::new();
+        cfg.reactor_config.discard_copy_actions = CopyPolicy::DiscardLeft;
+        cfg.generator_config = Generator::BTree(BTreeGen {
+            size: SizeSpec::Weighted(vec![(5, 1.0), (12, 3.0)]),
+            ..BTreeGen::new()
+        });
+        let parsed = Config::from_config_str(&cfg.to_config_str());
+        assert_eq!(
+            parsed.reactor_config.discard_copy_actions,
+            CopyPolicy::DiscardLeft
+        );
+        let Generator::BTree(gen) = parsed.generator_config else {
+            panic!("btree generator should round trip")
+        };
+        assert_eq!(gen.size, SizeSpec::Weighted(vec![(5, 1.0), (12, 3.0)]));
+    }
+
+    #[test]
+    fn unsampleable_sizes_are_rejected() {
+        for size in [
+            SizeSpec::Fixed(0),
+            SizeSpec::Uniform(12, 5),
+            SizeSpec::Uniform(0, 5),
+            SizeSpec::Weighted(vec![]),
+            SizeSpec::Weighted(vec![(5, 0.0), (12, 0.0)]),
+            SizeSpec::Weighted(vec![(5, -1.0), (12, 3.0)]),
+            SizeSpec::Weighted(vec![(5, f64::NAN)]),
+        ] {
+            assert!(size.validate().is_err(), "{:?} should be rejected", size);
+            let cfg = Config {
+                generator_config: Generator::BTree(BTreeGen {
+                    size,
+                    ..BTreeGen::new()
+                }),
+                ..Config::new()
+            };
+            let json = serde_json::to_string(&cfg).unwrap();
+            assert!(serde_json::from_str::<Config>(&json).is_err());
+        }
+        assert!(SizeSpec::Uniform(5, 5).validate().is_ok());
+        assert!(SizeSpec::Weighted(vec![(0, 0.0), (12, 3.0)])
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn reactor_rule_orders_are_checked() {
+        let mut cfg = Reactor::new();
+        cfg.rule_orders = vec![Some(ReductionOrder::Nor)];
+        let json = serde_json::to_string(&cfg).unwrap();
+        let parsed: Reactor = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.rule_orders, cfg.rule_orders);
+
+        cfg.rule_orders.push(None);
+        let json = serde_json::to_string(&cfg).unwrap();
+        assert!(serde_json::from_str::<Reactor>(&json).is_err());
+
+        // Strategies that stop short of normal form aren't accepted
+        assert!(serde_json::from_str::<ReductionOrder>(r#""CBV""#).is_err());
+    }
+}
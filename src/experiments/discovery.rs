@@ -11,9 +11,9 @@ use rand::random;
 
 use crate::{
     config::{self, ConfigSeed},
-    generators::BTreeGen,
-    lambda::recursive::reduce_with_limit,
-    utils::dump_series_to_file,
+    generators::{BTreeGen, Generator},
+    lambda::recursive::{reduce_with_limit, ReductionOutcome},
+    utils::{dump_series_to_file, SeriesOutput, SeriesWriter},
 };
 
 use super::{
@@ -25,15 +25,28 @@ use super::{
 
 fn experiment_gen(seed: ConfigSeed) -> BTreeGen {
     BTreeGen::from_config(&config::BTreeGen {
-        size: 20,
+        size: config::SizeSpec::Fixed(20),
         freevar_generation_probability: 0.2,
         standardization: crate::generators::Standardization::Prefix,
         n_max_free_vars: 6,
+        max_depth: None,
         seed,
     })
 }
 
+/// A sample of 5000 generated expressions, with the random seed it was generated from.
+fn random_sample() -> (ConfigSeed, Vec<Term>) {
+    let seed = ConfigSeed::new(random::<[u8; 32]>());
+    (seed, experiment_gen(seed).generate_n(5000))
+}
+
+/// A hand-picked sample, which is the same for every run and so has a blank seed.
+fn fixed_sample(sample: fn() -> Vec<Term>) -> impl Fn() -> (ConfigSeed, Vec<Term>) {
+    move || (ConfigSeed::blank(), sample())
+}
+
 pub fn measure_initial_population() {
+    let output = SeriesOutput::default();
     for (i, term) in [succ(), add()].iter().enumerate() {
         let series = (0..1000)
             .map(|_| {
@@ -45,40 +58,48 @@ pub fn measure_initial_population() {
                         let r = reduce_with_limit(&mut t, 1000, 8000);
                         (r, t)
                     })
-                    .filter(|(r, t)| r.is_ok() && t.is_isomorphic_to(&term))
+                    .filter(|(r, t)| {
+                        !matches!(r, ReductionOutcome::HitSizeLimit(_)) && t.is_isomorphic_to(&term)
+                    })
                     .count()
             })
             .collect::<Vec<_>>();
-        dump_series_to_file("initial_population_counts", &series, &[i])
+        dump_series_to_file(&output, "initial_population_counts", &series, &[i])
             .expect("Cannot write to file");
     }
 }
 
 fn parallel_run_executor<F>(fname: &str, isomorphics: &[Term], sample_generator: F)
 where
-    F: Fn() -> Vec<Term>,
+    F: Fn() -> (ConfigSeed, Vec<Term>),
 {
+    let output = SeriesOutput::default();
+    let seeds = SeriesWriter::new(&output, &format!("{fname}-seeds")).expect("Cannot open file");
     let mut futures = FuturesUnordered::new();
     let sample_size = 5000;
     for i in 0..1000 {
-        let random_seed = ConfigSeed::new(random::<[u8; 32]>());
-        let samples = sample_generator();
+        let (sample_seed, samples) = sample_generator();
 
         let params = RunParams {
             id: vec![i],
-            seed: random_seed,
+            reaction_seed: ConfigSeed::new(random::<[u8; 32]>()),
+            sample_seed,
             count_each_poll: isomorphics.to_vec(),
             perturbation_interval: 10,
             polling_interval: 1000,
             run_length: 100000,
             perturbation_size: 0,
         };
+        seeds
+            .append(&params.id, &params.seeds())
+            .expect("Cannot write to file");
 
         let run = general_run(vec![], samples, 0, sample_size, params);
         futures.push(spawn(run));
     }
+    let writer = SeriesWriter::new(&output, fname).expect("Cannot open file");
     while let Some((id, series)) = block_on(futures.next()) {
-        dump_series_to_file(fname, &series, &id).expect("Cannot write to file");
+        writer.append(&id, &series).expect("Cannot write to file");
     }
 }
 
@@ -88,25 +109,30 @@ fn parallel_test_run_executor<F, T>(
     sample_generator: F,
     test_generator: Vec<T>,
 ) where
-    F: Fn() -> Vec<Term>,
+    F: Fn() -> (ConfigSeed, Vec<Term>),
     T: Fn() -> Term + Send + Clone + 'static,
 {
+    let output = SeriesOutput::default();
+    let seeds = SeriesWriter::new(&output, &format!("{fname}-seeds")).expect("Cannot open file");
     let mut futures = FuturesUnordered::new();
     let sample_size = 4000;
     let test_size = 1000;
     for i in 0..1000 {
-        let random_seed = ConfigSeed::new(random::<[u8; 32]>());
-        let samples = sample_generator();
+        let (sample_seed, samples) = sample_generator();
 
         let params = RunParams {
             id: vec![i],
-            seed: random_seed,
+            reaction_seed: ConfigSeed::new(random::<[u8; 32]>()),
+            sample_seed,
             count_each_poll: isomorphics.to_vec(),
             perturbation_interval: 10,
             polling_interval: 1000,
             run_length: 100000,
             perturbation_size: 0,
         };
+        seeds
+            .append(&params.id, &params.seeds())
+            .expect("Cannot write to file");
 
         let run = general_test_run(
             vec![],
@@ -119,8 +145,9 @@ fn parallel_test_run_executor<F, T>(
         );
         futures.push(spawn(run));
     }
+    let writer = SeriesWriter::new(&output, fname).expect("Cannot open file");
     while let Some((id, series)) = block_on(futures.next()) {
-        dump_series_to_file(fname, &series, &id).expect("Cannot write to file");
+        writer.append(&id, &series).expect("Cannot write to file");
     }
 }
 
@@ -128,10 +155,7 @@ pub fn add_scc_population_from_random_inputs() {
     parallel_run_executor(
         "add_scc_population_from_random_inputs",
         &[succ(), add()],
-        || {
-            let random_seed = ConfigSeed::new(random::<[u8; 32]>());
-            experiment_gen(random_seed).generate_n(5000)
-        },
+        random_sample,
     )
 }
 
@@ -139,7 +163,7 @@ pub fn add_scc_population_from_ski_inputs() {
     parallel_run_executor(
         "add_scc_population_from_ski_inputs",
         &[succ(), add()],
-        || ski_sample(),
+        fixed_sample(ski_sample),
     )
 }
 
@@ -147,7 +171,7 @@ pub fn add_scc_population_from_skip_inputs() {
     parallel_run_executor(
         "add_scc_population_from_skip_inputs",
         &[succ(), add()],
-        || symmetric_skip_sample(),
+        fixed_sample(symmetric_skip_sample),
     )
 }
 
@@ -156,10 +180,7 @@ pub fn scc_population_from_random_inputs_with_tests() {
     parallel_test_run_executor(
         "scc_population_from_random_inputs_with_tests",
         &[succ(), add()],
-        || {
-            let random_seed = ConfigSeed::new(random::<[u8; 32]>());
-            experiment_gen(random_seed).generate_n(5000)
-        },
+        random_sample,
         tests,
     )
 }
@@ -169,10 +190,7 @@ pub fn add_population_from_random_inputs_with_tests() {
     parallel_test_run_executor(
         "add_population_from_random_inputs_with_tests",
         &[succ(), add()],
-        || {
-            let random_seed = ConfigSeed::new(random::<[u8; 32]>());
-            experiment_gen(random_seed).generate_n(5000)
-        },
+        random_sample,
         tests,
     )
 }
@@ -185,10 +203,7 @@ pub fn add_population_from_random_inputs_with_add_succ_tests() {
     parallel_test_run_executor(
         "add_population_from_random_inputs_with_add_succ_tests",
         &[succ(), add()],
-        || {
-            let random_seed = ConfigSeed::new(random::<[u8; 32]>());
-            experiment_gen(random_seed).generate_n(5000)
-        },
+        random_sample,
         tests,
     )
 }
@@ -199,7 +214,7 @@ pub fn scc_population_from_ski_inputs_with_tests() {
     parallel_test_run_executor(
         "scc_population_from_ski_inputs_with_tests",
         &[succ(), add()],
-        || ski_sample(),
+        fixed_sample(ski_sample),
         tests,
     )
 }
@@ -209,7 +224,7 @@ pub fn add_population_from_ski_inputs_with_tests() {
     parallel_test_run_executor(
         "add_random_pop_series_test",
         &[succ(), add()],
-        || ski_sample(),
+        fixed_sample(ski_sample),
         tests,
     )
 }
@@ -223,7 +238,7 @@ pub fn add_population_from_ski_inputs_with_add_succ_tests() {
     parallel_test_run_executor(
         "add_ski_addsucc_tests",
         &[succ(), add(), coadd()],
-        || ski_sample(),
+        fixed_sample(ski_sample),
         tests,
     )
 }
@@ -237,7 +252,7 @@ pub fn add_population_from_ski_inputs_with_batchedadd_succ_tests() {
     parallel_test_run_executor(
         "add_ski_batchedaddsucc_tests",
         &[succ(), add(), coadd()],
-        || ski_sample(),
+        fixed_sample(ski_sample),
         tests,
     )
 }
@@ -251,7 +266,7 @@ pub fn add_population_from_skip_inputs_with_add_succ_tests() {
     parallel_test_run_executor(
         "add_skip_addsucc_tests",
         &[succ(), add(), coadd()],
-        || symmetric_skip_sample(),
+        fixed_sample(symmetric_skip_sample),
         tests,
     )
 }
@@ -262,7 +277,7 @@ pub fn addtwo_population_from_ski_inputs_with_addtwo_tests() {
     parallel_test_run_executor(
         "addtwo_ski_addtwo_tests",
         &[succ(), addtwo()],
-        || ski_sample(),
+        fixed_sample(ski_sample),
         tests,
     )
 }
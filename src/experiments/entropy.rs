@@ -7,30 +7,21 @@ use lambda_calculus::Term;
 
 use crate::{
     config::{self, ConfigSeed},
-    generators::BTreeGen,
+    generators::{BTreeGen, Generator},
     lambda::recursive::LambdaSoup,
 };
 
 fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
-    LambdaSoup::from_config(&config::Reactor {
-        rules: vec![String::from("\\x.\\y.x y")],
-        discard_copy_actions: false,
-        discard_identity: false,
-        discard_free_variable_expressions: true,
-        maintain_constant_population_size: true,
-        discard_parents: false,
-        reduction_cutoff: 8000,
-        size_cutoff: 1000,
-        seed,
-    })
+    LambdaSoup::from_config(&config::presets::standard_reactor(seed))
 }
 
 fn experiment_gen(seed: ConfigSeed) -> BTreeGen {
     BTreeGen::from_config(&config::BTreeGen {
-        size: 20,
+        size: config::SizeSpec::Fixed(20),
         freevar_generation_probability: 0.2,
         standardization: crate::generators::Standardization::Prefix,
         n_max_free_vars: 6,
+        max_depth: None,
         seed,
     })
 }
@@ -42,8 +33,8 @@ async fn simulate_soup(
 ) -> (LambdaSoup, usize, f32) {
     let mut soup = experiment_soup(ConfigSeed::new([0; 32]));
     soup.add_lambda_expressions(sample);
-    let n_successes = soup.simulate_for(run_length, false);
-    let failure_rate = 1f32 - n_successes as f32 / run_length as f32;
+    soup.simulate_for(run_length, false);
+    let failure_rate = soup.failure_rate();
     (soup, id, failure_rate)
 }
 
@@ -53,23 +44,22 @@ async fn simulate_soup_and_produce_entropies(
     run_length: usize,
     polling_interval: usize,
 ) -> (usize, Vec<f32>) {
-    let mut seed: [u8; 32] = [0; 32];
-    let bytes = id.to_le_bytes();
-    seed[..bytes.len()].copy_from_slice(&bytes);
-    let mut soup = experiment_soup(ConfigSeed::new([0; 32]));
+    let mut soup = experiment_soup(ConfigSeed::new([0; 32]).derive(id as u64));
     soup.add_lambda_expressions(sample);
+    // No test particles or eta-merging here, so the cached entropy is exact
     let data = soup.simulate_and_poll(run_length, polling_interval, false, |s: &LambdaSoup| {
-        s.population_entropy()
+        s.entropy_cached()
     });
     (id, data)
 }
 
-pub fn entropy_time_series() {
+/// Entropy of 1000 soups, polled every 1000 reactions. Returns `(soup id, entropies)` rows in
+/// the order the soups finish.
+pub fn entropy_time_series() -> Vec<(usize, Vec<f32>)> {
     let mut gen = experiment_gen(ConfigSeed::new([0; 32]));
     let mut futures = FuturesUnordered::new();
     let run_length = 10000000;
     let polling_interval = 1000;
-    let polls = run_length / polling_interval;
     for i in 0..1000 {
         let sample = gen.generate_n(10000);
         futures.push(spawn(simulate_soup_and_produce_entropies(
@@ -80,21 +70,16 @@ pub fn entropy_time_series() {
         )));
     }
 
-    print!("Soup, ");
-    for i in 0..polls {
-        print!("{}, ", i)
-    }
-    println!();
-    while let Some((id, data)) = block_on(futures.next()) {
-        print!("{}, ", id);
-        for i in data {
-            print!("{}, ", i)
-        }
-        println!();
+    let mut rows = Vec::new();
+    while let Some(row) = block_on(futures.next()) {
+        rows.push(row);
     }
+    rows
 }
 
-pub fn entropy_and_failures() {
+/// Final entropy and failure rate of 1000 soups. Returns `(soup id, entropy, failure rate)`
+/// rows in the order the soups finish.
+pub fn entropy_and_failures() -> Vec<(usize, f32, f32)> {
     let mut gen = experiment_gen(ConfigSeed::new([0; 32]));
     let mut futures = FuturesUnordered::new();
     for i in 0..1000 {
@@ -102,24 +87,25 @@ pub fn entropy_and_failures() {
         futures.push(spawn(simulate_soup(sample.into_iter(), i, 10000000)));
     }
 
-    let mut data = Vec::new();
-    println!("Soup, Entropy, Failure rate");
+    let mut rows = Vec::new();
     while let Some((soup, id, failure_rate)) = block_on(futures.next()) {
-        let entropy = soup.population_entropy();
-        println!("{}, {}, {}", id, entropy, failure_rate);
-        data.push(entropy);
+        rows.push((id, soup.population_entropy(), failure_rate));
     }
+    rows
 }
 
-pub fn sync_entropy_and_failures() {
+/// Final entropy of 100 soups, simulated one after the other. Returns `(soup id, entropy)`
+/// rows.
+pub fn sync_entropy_and_failures() -> Vec<(usize, f32)> {
     let mut gen = experiment_gen(ConfigSeed::new([0; 32]));
 
-    for i in 0..100 {
-        let sample = gen.generate_n(1000);
-        let mut soup = experiment_soup(ConfigSeed::new([0; 32]));
-        soup.add_lambda_expressions(sample);
-        soup.simulate_for(100000, false);
-        let entropy = soup.population_entropy();
-        println!("{}: {}", i, entropy);
-    }
+    (0..100)
+        .map(|i| {
+            let sample = gen.generate_n(1000);
+            let mut soup = experiment_soup(ConfigSeed::new([0; 32]));
+            soup.add_lambda_expressions(sample);
+            soup.simulate_for(100000, false);
+            (i, soup.population_entropy())
+        })
+        .collect()
 }
@@ -4,33 +4,31 @@
 use async_std::task::{block_on, spawn};
 use futures::stream::{FuturesUnordered, StreamExt};
 use lambda_calculus::{data::num::church::succ, Term};
+use log::info;
 use rand::random;
 
 use crate::{
     config::{self, ConfigSeed},
     lambda::recursive::LambdaSoup,
-    utils::dump_series_to_file,
+    utils::{encode_hex, SeriesOutput, SeriesWriter},
 };
 
-use super::magic_test_function::{asymmetric_skip_sample, test_succ};
+use super::magic_test_function::{asymmetric_skip_sample, poll_block, test_succ};
 
 fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
-    LambdaSoup::from_config(&config::Reactor {
-        rules: vec![String::from("\\x.\\y.x y")],
-        discard_copy_actions: false,
-        discard_identity: false,
-        discard_free_variable_expressions: true,
-        maintain_constant_population_size: true,
-        discard_parents: false,
-        reduction_cutoff: 8000,
-        size_cutoff: 1000,
-        seed,
-    })
+    LambdaSoup::from_config(&config::presets::standard_reactor(seed))
 }
 
-pub(super) struct RunParams {
+/// Parameters of a single run. Randomness is split into two independent streams so that one
+/// can be varied while the other is held fixed: `sample_seed` is the seed the initial
+/// expressions were generated from (blank for hand-picked samples), and `reaction_seed` seeds
+/// the soup's reaction draws. Never reuse one seed for both; to run one sample under many
+/// reaction seeds, keep `sample_seed` and vary `reaction_seed`.
+#[derive(Clone)]
+pub struct RunParams {
     pub id: Vec<usize>,
-    pub seed: ConfigSeed,
+    pub reaction_seed: ConfigSeed,
+    pub sample_seed: ConfigSeed,
     pub run_length: usize,
     pub polling_interval: usize,
     pub perturbation_interval: usize,
@@ -38,6 +36,15 @@ pub(super) struct RunParams {
     pub count_each_poll: Vec<Term>,
 }
 
+impl RunParams {
+    /// The reaction and sample seeds in hex, empty if blank, to record next to the run's
+    /// series so that it can be reproduced.
+    pub fn seeds(&self) -> [String; 2] {
+        [self.reaction_seed, self.sample_seed]
+            .map(|seed| seed.seed().map_or(String::new(), |s| encode_hex(&s)))
+    }
+}
+
 // Returns (id, populations), where id is a vec of usizes and populations is a vec of
 // (count, isomorphics). Here, count is the current population of recursive functions in the soup,
 // and isomorphics is a list of populations of terms isomorphic to terms in params.count_each_poll.
@@ -53,7 +60,7 @@ pub(super) async fn general_test_run<F>(
 where
     F: Fn() -> Term,
 {
-    let mut soup = experiment_soup(params.seed);
+    let mut soup = experiment_soup(params.reaction_seed);
 
     let prefix_iter = prefix.iter().cycle();
     let sample_iter = sample.into_iter().cycle();
@@ -62,19 +69,16 @@ where
     soup.add_lambda_expressions(prefix_iter.cloned().take(n_prefix));
     soup.add_lambda_expressions(sample_iter.clone().take(n_samples));
     soup.add_test_expressions(test_iter.clone().take(n_tests));
+    let targets = soup.term_set(&params.count_each_poll);
 
     let populations = (0..params.perturbation_interval)
         .flat_map(|i| {
-            let pops = soup.simulate_and_poll(
+            let pops = poll_block(
+                &mut soup,
                 params.run_length / params.perturbation_interval,
                 params.polling_interval,
-                false,
                 |s| {
-                    let isomorphics = params
-                        .count_each_poll
-                        .iter()
-                        .map(|t| s.population_of(t))
-                        .collect();
+                    let isomorphics = s.population_in(&targets);
                     let n_recursive = s.expressions().filter(|e| e.is_recursive()).count();
                     (n_recursive, isomorphics)
                 },
@@ -83,7 +87,7 @@ where
             let n_remaining = n_tests - soup.expressions().filter(|e| e.is_recursive()).count();
             soup.perturb_test_expressions(n_remaining, test_iter.clone().take(n_remaining));
             soup.perturb_lambda_expressions(params.perturbation_size, sample_iter.clone());
-            println!("Soup {:?} {}0% done", params.id, i + 1);
+            info!("Soup {:?} {}0% done", params.id, i + 1);
 
             pops
         })
@@ -98,77 +102,136 @@ pub(super) async fn general_run(
     n_samples: usize,
     params: RunParams,
 ) -> (Vec<usize>, Vec<(usize, Vec<usize>)>) {
-    let mut soup = experiment_soup(params.seed);
+    let mut soup = experiment_soup(params.reaction_seed);
 
     let prefix_iter = prefix.iter().cycle();
     let sample_iter = sample.iter().cycle();
 
     soup.add_lambda_expressions(prefix_iter.cloned().take(n_prefix));
     soup.add_lambda_expressions(sample_iter.cloned().take(n_samples));
+    let targets = soup.term_set(&params.count_each_poll);
 
     let populations = (0..params.perturbation_interval)
         .flat_map(|i| {
-            let pops = soup.simulate_and_poll(
+            let pops = poll_block(
+                &mut soup,
                 params.run_length / params.perturbation_interval,
                 params.polling_interval,
-                false,
                 |s| {
-                    let isomorphics = params
-                        .count_each_poll
-                        .iter()
-                        .map(|t| s.population_of(t))
-                        .collect();
+                    let isomorphics = s.population_in(&targets);
                     let n_recursive = s.expressions().filter(|e| e.is_recursive()).count();
                     (n_recursive, isomorphics)
                 },
             );
 
-            println!("Soup {:?} {}0% done", params.id, i + 1);
+            info!("Soup {:?} {}0% done", params.id, i + 1);
             pops
         })
         .collect();
     (params.id, populations)
 }
 
-pub fn kinetic_succ_experiment() {
-    let mut futures = FuturesUnordered::new();
+/// Run the selection protocol over every combination of initial `good_fracs` (the fraction of
+/// the soup seeded with `target`) and `test_fracs` (the fraction seeded with `tests`), `seeds`
+/// times each. The rest of each soup is filled by cycling through `samples`, so every run shares
+/// one sample. `params` is used as a template for every run; its `id` is replaced per run, and
+/// each run's `reaction_seed` is derived from the template's for its seed index, so a blank
+/// template seed gives unseeded runs. Results are appended to `fname`, and each run's seeds to
+/// `{fname}-seeds`. Panics unless every fraction is in `[0, 1]` and every pair of fractions
+/// leaves a non-negative rest.
+pub fn sweep_selection(
+    fname: &str,
+    target: Term,
+    tests: Vec<fn() -> Term>,
+    samples: Vec<Term>,
+    good_fracs: &[f64],
+    test_fracs: &[f64],
+    seeds: usize,
+    sample_size: usize,
+    params: RunParams,
+) {
+    let max_frac = |fracs: &[f64]| fracs.iter().copied().fold(0.0, f64::max);
+    assert!(
+        good_fracs
+            .iter()
+            .chain(test_fracs)
+            .all(|f| (0.0..=1.0).contains(f)),
+        "fractions must be in [0, 1]"
+    );
+    assert!(
+        max_frac(good_fracs) + max_frac(test_fracs) <= 1.0,
+        "good and test fractions must not add up to more than 1"
+    );
 
-    let sample_size = 5000;
-    let good_fracs = [
-        0.0, 0.0002, 0.0004, 0.0008, 0.0016, 0.0032, 0.0064, 0.0128, 0.0256, 0.0512, 0.1024,
-    ];
-    let test_fracs = [
-        0.0, 0.05, 0.10, 0.15, 0.20, 0.25, 0.30, 0.35, 0.40, 0.45, 0.50, 0.55, 0.60, 0.65, 0.70,
-        0.75, 0.80,
-    ];
+    let mut futures = FuturesUnordered::new();
+    let output = SeriesOutput::default();
+    let seeds_file = format!("{fname}-seeds");
+    let seed_writer = SeriesWriter::new(&output, &seeds_file).expect("Cannot open file");
 
     for (i, good_frac) in good_fracs.iter().enumerate() {
         for (j, test_frac) in test_fracs.iter().enumerate() {
-            for seed in 0..100 {
+            for seed in 0..seeds {
                 let n_good = (good_frac * sample_size as f64) as usize;
                 let n_test = (test_frac * sample_size as f64) as usize;
                 let n_rest = sample_size - (n_good + n_test);
 
-                let goods = vec![succ()];
-                let tests = vec![|| test_succ(random::<usize>() % 20)];
-                let samples = asymmetric_skip_sample();
                 let params = RunParams {
                     id: vec![i, j, seed],
-                    seed: ConfigSeed::new([seed as u8; 32]),
-                    count_each_poll: vec![succ()],
-                    perturbation_interval: 10,
-                    polling_interval: 1000,
-                    run_length: 100000,
-                    perturbation_size: 200,
+                    reaction_seed: params.reaction_seed.derive(seed as u64),
+                    ..params.clone()
                 };
-
-                let run = general_test_run(goods, samples, tests, n_good, n_rest, n_test, params);
+                seed_writer
+                    .append(&params.id, &params.seeds())
+                    .expect("Cannot write to file");
+
+                let run = general_test_run(
+                    vec![target.clone()],
+                    samples.clone(),
+                    tests.clone(),
+                    n_good,
+                    n_rest,
+                    n_test,
+                    params,
+                );
                 futures.push(spawn(run));
             }
         }
     }
-    let fname = "kinetic-scc-output";
+    let writer = SeriesWriter::new(&output, fname).expect("Cannot open file");
     while let Some((id, series)) = block_on(futures.next()) {
-        dump_series_to_file(fname, &series, &id).expect("Cannot write to file");
+        writer.append(&id, &series).expect("Cannot write to file");
     }
 }
+
+pub fn kinetic_succ_experiment() {
+    let good_fracs = [
+        0.0, 0.0002, 0.0004, 0.0008, 0.0016, 0.0032, 0.0064, 0.0128, 0.0256, 0.0512, 0.1024,
+    ];
+    let test_fracs = [
+        0.0, 0.05, 0.10, 0.15, 0.20, 0.25, 0.30, 0.35, 0.40, 0.45, 0.50, 0.55, 0.60, 0.65, 0.70,
+        0.75, 0.80,
+    ];
+    let tests: Vec<fn() -> Term> = vec![|| test_succ(random::<usize>() % 20)];
+    let params = RunParams {
+        id: vec![],
+        reaction_seed: ConfigSeed::new([0; 32]),
+        sample_seed: ConfigSeed::blank(),
+        count_each_poll: vec![succ()],
+        perturbation_interval: 10,
+        polling_interval: 1000,
+        run_length: 100000,
+        perturbation_size: 200,
+    };
+
+    sweep_selection(
+        "kinetic-scc-output",
+        succ(),
+        tests,
+        asymmetric_skip_sample(),
+        &good_fracs,
+        &test_fracs,
+        100,
+        5000,
+        params,
+    );
+}
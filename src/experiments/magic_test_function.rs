@@ -13,26 +13,31 @@ use lambda_calculus::{
     IntoChurchNum,
     Term::{self, Var},
 };
+use log::info;
 use rand::random;
 
 use crate::{
     config::{self, ConfigSeed},
     lambda::recursive::{has_two_args, is_truthy, uses_both_arguments, LambdaSoup},
-    utils::{dump_series_to_file, read_inputs},
+    utils::{read_inputs, SampleSpec, SeriesOutput, SeriesWriter},
 };
 
 fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
-    LambdaSoup::from_config(&config::Reactor {
-        rules: vec![String::from("\\x.\\y.x y")],
-        discard_copy_actions: false,
-        discard_identity: false,
-        discard_free_variable_expressions: true,
-        maintain_constant_population_size: true,
-        discard_parents: false,
-        reduction_cutoff: 8000,
-        size_cutoff: 1000,
-        seed,
-    })
+    LambdaSoup::from_config(&config::presets::standard_reactor(seed))
+}
+
+/// Run one block of `n` reactions between perturbations, returning `f` of the soup after
+/// reactions `0`, `polling_interval`, `2 * polling_interval`, ... Unlike `simulate_and_poll`,
+/// the end of the block isn't polled as well, so a run made of consecutive blocks samples at
+/// the same pace throughout.
+pub(super) fn poll_block<R>(
+    soup: &mut LambdaSoup,
+    n: usize,
+    polling_interval: usize,
+    f: impl Fn(&LambdaSoup) -> R,
+) -> Vec<R> {
+    let snapshots = soup.snapshots(n, polling_interval, false, f);
+    snapshots.map(|(_, datum)| datum).collect()
 }
 
 pub fn coadd() -> Term {
@@ -80,12 +85,12 @@ pub fn test_addtwo(a: usize) -> Term {
 }
 
 pub(super) fn asymmetric_skip_sample() -> Vec<Term> {
-    let mut sample = vec![];
-    sample.append(&mut vec![S(); 10]);
-    sample.append(&mut vec![K(); 10]);
-    sample.append(&mut vec![I(); 10]);
-    sample.append(&mut vec![p132(); 1]);
-    sample
+    SampleSpec::new()
+        .count(S(), 10)
+        .count(K(), 10)
+        .count(I(), 10)
+        .count(p132(), 1)
+        .build()
 }
 
 pub(super) fn symmetric_skip_sample() -> Vec<Term> {
@@ -118,12 +123,12 @@ async fn add_magic_tests(
     run_length: usize,
     polling_interval: usize,
 ) -> (usize, Vec<(usize, usize, usize)>) {
-    let mut soup = experiment_soup(ConfigSeed::new([id as u8; 32]));
+    let mut soup = experiment_soup(ConfigSeed::new([0; 32]).derive(id as u64));
     soup.add_lambda_expressions(sample);
     soup.add_test_expressions(tests);
     let mut populations = Vec::new();
     for i in 0..10 {
-        let pops = soup.simulate_and_poll(run_length / 10, polling_interval, false, |s| {
+        let pops = poll_block(&mut soup, run_length / 10, polling_interval, |s| {
             (
                 s.expressions().filter(|e| e.is_recursive()).count(),
                 s.population_of(&succ()),
@@ -144,7 +149,7 @@ async fn add_magic_tests(
         let skips = asymmetric_skip_sample();
         soup.perturb_lambda_expressions(200, skips);
 
-        println!("Soup {id} {}0% done", i + 1);
+        info!("Soup {id} {}0% done", i + 1);
     }
     (id, populations)
 }
@@ -156,12 +161,12 @@ async fn succ_magic_tests(
     run_length: usize,
     polling_interval: usize,
 ) -> (usize, Vec<(usize, usize, usize)>) {
-    let mut soup = experiment_soup(ConfigSeed::new([id as u8; 32]));
+    let mut soup = experiment_soup(ConfigSeed::new([0; 32]).derive(id as u64));
     soup.add_lambda_expressions(sample);
     soup.add_test_expressions(tests);
     let mut populations = Vec::new();
     for i in 0..10 {
-        let pops = soup.simulate_and_poll(run_length / 10, polling_interval, false, |s| {
+        let pops = poll_block(&mut soup, run_length / 10, polling_interval, |s| {
             (
                 s.expressions().filter(|e| e.is_recursive()).count(),
                 s.population_of(&succ()),
@@ -179,7 +184,7 @@ async fn succ_magic_tests(
         let skips = asymmetric_skip_sample();
         soup.perturb_lambda_expressions(200, skips);
 
-        println!("Soup {id} {}0% done", i + 1);
+        info!("Soup {id} {}0% done", i + 1);
     }
     (id, populations)
 }
@@ -255,8 +260,9 @@ pub fn add_search_with_test() {
     }
 
     let fname = "add-search-output";
+    let writer = SeriesWriter::new(&SeriesOutput::default(), fname).expect("Cannot open file");
     while let Some((id, series)) = block_on(futures.next()) {
-        dump_series_to_file(fname, &series, &[id]).expect("Cannot write to file");
+        writer.append(&[id], &series).expect("Cannot write to file");
     }
 }
 
@@ -284,8 +290,9 @@ pub fn succ_search_with_test() {
     }
 
     let fname = "scc-search-output";
+    let writer = SeriesWriter::new(&SeriesOutput::default(), fname).expect("Cannot open file");
     while let Some((id, series)) = block_on(futures.next()) {
-        dump_series_to_file(fname, &series, &[id]).expect("Cannot write to file");
+        writer.append(&[id], &series).expect("Cannot write to file");
     }
 }
 
@@ -7,110 +7,141 @@ use lambda_calculus::{app, Term};
 
 use crate::{
     config::{self, ConfigSeed},
-    generators::BTreeGen,
+    generators::{BTreeGen, Generator},
     lambda::recursive::{reduce_with_limit, LambdaSoup},
 };
 
 fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
-    LambdaSoup::from_config(&config::Reactor {
-        rules: vec![String::from("\\x.\\y.x y")],
-        discard_copy_actions: false,
-        discard_identity: false,
-        discard_free_variable_expressions: true,
-        maintain_constant_population_size: true,
-        discard_parents: false,
-        reduction_cutoff: 8000,
-        size_cutoff: 1000,
-        seed,
-    })
+    LambdaSoup::from_config(&config::presets::standard_reactor(seed))
 }
 
 fn experiment_gen(seed: ConfigSeed) -> BTreeGen {
     BTreeGen::from_config(&config::BTreeGen {
-        size: 20,
+        size: config::SizeSpec::Fixed(20),
         freevar_generation_probability: 0.2,
         standardization: crate::generators::Standardization::Prefix,
         n_max_free_vars: 6,
+        max_depth: None,
         seed,
     })
 }
 
-fn xorset_test(a: &Term, b: &Term) -> bool {
-    if a.is_isomorphic_to(b) {
-        return false;
+/// The expected behavior of a small alphabet of terms under application: the alphabet
+/// `terms` satisfies the spec if `terms[i] terms[j]` reduces to `terms[table[i][j]]` for every
+/// ordered pair `(i, j)`. With two terms standing in for true and false, the table is the truth
+/// table of a binary operation, so AND, OR, XOR, NAND, ... are all specs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BehaviorSpec {
+    table: Vec<Vec<usize>>,
+    rlimit: usize,
+    slimit: usize,
+}
+
+impl BehaviorSpec {
+    /// A spec from a square `table` of alphabet indices, with reductions limited to 512 steps
+    /// and size 1024.
+    pub fn new(table: Vec<Vec<usize>>) -> Self {
+        let n = table.len();
+        assert!(
+            table
+                .iter()
+                .all(|row| row.len() == n && row.iter().all(|&k| k < n)),
+            "behavior table must be square and index into the alphabet"
+        );
+        BehaviorSpec {
+            table,
+            rlimit: 512,
+            slimit: 1024,
+        }
     }
 
-    let mut aa = app(a.clone(), a.clone());
-    let mut ab = app(a.clone(), b.clone());
-    let mut ba = app(b.clone(), a.clone());
-    let mut bb = app(b.clone(), b.clone());
+    /// A spec for a binary operation `op` on an alphabet of `n` terms.
+    pub fn from_op(n: usize, op: impl Fn(usize, usize) -> usize) -> Self {
+        Self::new((0..n).map(|i| (0..n).map(|j| op(i, j)).collect()).collect())
+    }
 
-    let _ = reduce_with_limit(&mut aa, 512, 1024);
-    let _ = reduce_with_limit(&mut ba, 512, 1024);
-    let _ = reduce_with_limit(&mut ab, 512, 1024);
-    let _ = reduce_with_limit(&mut bb, 512, 1024);
+    /// Limit each reduction to `rlimit` steps and size `slimit`.
+    pub fn with_limits(mut self, rlimit: usize, slimit: usize) -> Self {
+        self.rlimit = rlimit;
+        self.slimit = slimit;
+        self
+    }
 
-    aa.is_isomorphic_to(a)
-        && ab.is_isomorphic_to(b)
-        && ba.is_isomorphic_to(b)
-        && bb.is_isomorphic_to(a)
-}
+    /// Number of terms in the alphabet.
+    pub fn n_terms(&self) -> usize {
+        self.table.len()
+    }
 
-fn not_xorset_test(a: &Term, b: &Term) -> bool {
-    if a.is_isomorphic_to(b) {
-        return false;
+    /// `aa = a`, `ab = ba = b`, `bb = a`: XOR, with `a` as false.
+    pub fn xorset() -> Self {
+        Self::from_op(2, |i, j| i ^ j)
     }
 
-    let mut aa = app(a.clone(), a.clone());
-    let mut ab = app(a.clone(), b.clone());
-    let mut ba = app(b.clone(), a.clone());
-    let mut bb = app(b.clone(), b.clone());
+    /// `aa = ab = ba = b`, `bb = a`: NAND, with `a` as false.
+    pub fn not_xorset() -> Self {
+        Self::from_op(2, |i, j| 1 - (i & j))
+    }
+}
 
-    let _ = reduce_with_limit(&mut aa, 512, 1024);
-    let _ = reduce_with_limit(&mut ba, 512, 1024);
-    let _ = reduce_with_limit(&mut ab, 512, 1024);
-    let _ = reduce_with_limit(&mut bb, 512, 1024);
+/// Whether `terms`, which must be pairwise distinct, behave as `spec` says. Products that do
+/// not reach normal form within the spec's limits are compared as they are.
+pub fn satisfies(terms: &[Term], spec: &BehaviorSpec) -> bool {
+    if terms.len() != spec.n_terms() {
+        return false;
+    }
+    for (i, a) in terms.iter().enumerate() {
+        if terms[..i].iter().any(|b| a.is_isomorphic_to(b)) {
+            return false;
+        }
+    }
+    spec.table.iter().enumerate().all(|(i, row)| {
+        row.iter().enumerate().all(|(j, &k)| {
+            let mut product = app(terms[i].clone(), terms[j].clone());
+            let _ = reduce_with_limit(&mut product, spec.rlimit, spec.slimit);
+            product.is_isomorphic_to(&terms[k])
+        })
+    })
+}
 
-    aa.is_isomorphic_to(b)
-        && ab.is_isomorphic_to(b)
-        && ba.is_isomorphic_to(b)
-        && bb.is_isomorphic_to(a)
+/// The first ordered selection of distinct `terms` that satisfies `spec`, if any.
+fn find_satisfying(terms: &[Term], spec: &BehaviorSpec) -> Option<Vec<Term>> {
+    let mut chosen = Vec::with_capacity(spec.n_terms());
+    search(terms, spec, &mut chosen)
 }
 
-fn pairwise_compare<F>(terms: &[Term], test: &F, symmetric: bool) -> Option<(Term, Term)>
-where
-    F: Fn(&Term, &Term) -> bool,
-{
-    for (i, t1) in terms.iter().enumerate() {
-        for (j, t2) in terms.iter().enumerate() {
-            if test(t1, t2) {
-                return Some((t1.clone(), t2.clone()));
-            }
-            if j >= i && symmetric {
-                break;
-            }
+fn search(terms: &[Term], spec: &BehaviorSpec, chosen: &mut Vec<usize>) -> Option<Vec<Term>> {
+    if chosen.len() == spec.n_terms() {
+        let alphabet = chosen.iter().map(|&i| terms[i].clone()).collect::<Vec<_>>();
+        return satisfies(&alphabet, spec).then_some(alphabet);
+    }
+    for i in 0..terms.len() {
+        if chosen.contains(&i) {
+            continue;
+        }
+        chosen.push(i);
+        let found = search(terms, spec, chosen);
+        chosen.pop();
+        if found.is_some() {
+            return found;
         }
     }
     None
 }
 
-async fn test_and_search_for_function<F>(
+async fn test_and_search_for_function(
     sample: impl Iterator<Item = Term>,
     id: usize,
     run_length: usize,
     polling_interval: usize,
-    test: F,
-) -> (usize, Vec<Option<(Term, Term)>>)
-where
-    F: Fn(&Term, &Term) -> bool,
-{
+    spec: BehaviorSpec,
+) -> (usize, Vec<Option<Vec<Term>>>) {
     let mut soup = experiment_soup(ConfigSeed::new([0; 32]));
     soup.add_lambda_expressions(sample);
     let check_series =
         soup.simulate_and_poll_with_killer(run_length, polling_interval, false, |s| {
             let bests = s.k_most_frequent_exprs(10);
-            let pairs = pairwise_compare(&bests, &test, false);
-            (pairs.clone(), pairs.is_some())
+            let found = find_satisfying(&bests, &spec);
+            (found.clone(), found.is_some())
         });
     (id, check_series)
 }
@@ -127,7 +158,7 @@ pub fn look_for_xorset() {
             i,
             run_length,
             polling_interval,
-            xorset_test,
+            BehaviorSpec::xorset(),
         )));
     }
 
@@ -156,7 +187,7 @@ pub fn look_for_not_xorset() {
             i,
             run_length,
             polling_interval,
-            not_xorset_test,
+            BehaviorSpec::not_xorset(),
         )));
     }
 
@@ -172,3 +203,20 @@ pub fn look_for_not_xorset() {
         println!();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::combinators::{I, K};
+
+    use super::{satisfies, BehaviorSpec};
+
+    #[test]
+    fn specs_check_every_product() {
+        // I I = I, but K I is not in the alphabet
+        assert!(satisfies(&[I()], &BehaviorSpec::new(vec![vec![0]])));
+        assert!(!satisfies(&[I(), K()], &BehaviorSpec::from_op(2, |_, j| j)));
+        assert!(!satisfies(&[I(), I()], &BehaviorSpec::xorset()));
+        assert_eq!(BehaviorSpec::xorset().table, [[0, 1], [1, 0]]);
+        assert_eq!(BehaviorSpec::not_xorset().table, [[1, 1], [1, 0]]);
+    }
+}
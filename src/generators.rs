@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+
+use lambda_calculus::combinators::{I, K, S};
 use lambda_calculus::Term::{self, Abs};
 use rand::{seq::SliceRandom, Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
@@ -5,6 +8,34 @@ use serde::{Deserialize, Serialize};
 
 use crate::config;
 use crate::config::GenConfig;
+use crate::lambda::recursive::{has_two_args, uses_both_arguments};
+use crate::lambda::{close_term, term_depth};
+
+/// A source of random lambda expressions.
+pub trait Generator {
+    /// Generate a single lambda expression.
+    fn generate(&mut self) -> Term;
+
+    /// Generate `n` lambda expressions.
+    fn generate_n(&mut self, n: usize) -> Vec<Term> {
+        let mut v = Vec::with_capacity(n);
+        for _ in 0..n {
+            v.push(self.generate())
+        }
+        v
+    }
+
+    /// The seed the generator was created with.
+    fn seed(&self) -> [u8; 32];
+}
+
+/// Build whichever generator `cfg` selects.
+pub fn from_config(cfg: &config::Generator) -> Box<dyn Generator> {
+    match cfg {
+        config::Generator::BTree(gen_cfg) => Box::new(BTreeGen::from_config(gen_cfg)),
+        config::Generator::Fontana(gen_cfg) => Box::new(FontanaGen::from_config(gen_cfg)),
+    }
+}
 
 struct BTree {
     n: u32,
@@ -12,7 +43,7 @@ struct BTree {
     right: Option<Box<BTree>>,
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Standardization {
     Prefix,
     Postfix,
@@ -72,16 +103,69 @@ impl BTree {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct BTreeGen {
-    n: u32,
+    size: config::SizeSpec,
     freevar_p: f64,
     max_free_vars: u32,
     std: Standardization,
+    max_depth: Option<usize>,
 
     seed: [u8; 32],
     rng: ChaCha8Rng,
 }
 
+/// How many trees `BTreeGen` draws for one term before giving up on `max_depth`.
+const MAX_DEPTH_RETRIES: usize = 1000;
+
+impl Generator for BTreeGen {
+    /// Generate a term, rejecting trees deeper than `max_depth` if it is set. If
+    /// `MAX_DEPTH_RETRIES` trees in a row are too deep, the bound is likely out of reach for the
+    /// configured size, and the shallowest of them is kept.
+    fn generate(&mut self) -> Term {
+        let Some(max_depth) = self.max_depth else {
+            return self.generate_unbounded();
+        };
+        let mut shallowest: Option<(usize, Term)> = None;
+        for _ in 0..MAX_DEPTH_RETRIES {
+            let candidate = self.generate_unbounded();
+            let depth = term_depth(&candidate);
+            if depth <= max_depth {
+                return candidate;
+            }
+            if shallowest.as_ref().is_none_or(|(d, _)| depth < *d) {
+                shallowest = Some((depth, candidate));
+            }
+        }
+        let (_, term) = shallowest.expect("MAX_DEPTH_RETRIES is positive");
+        term
+    }
+
+    fn seed(&self) -> [u8; 32] {
+        self.seed
+    }
+}
+
+impl BTreeGen {
+    fn generate_unbounded(&mut self) -> Term {
+        let n = self.sample_size();
+        assert!(
+            n > 0,
+            "btree generator does not produce zero-sized expressions."
+        );
+        let mut permutation = (0..n).collect::<Vec<u32>>();
+        permutation.shuffle(&mut self.rng);
+        let mut tree = BTree::new(permutation[0]);
+        permutation.iter().skip(1).for_each(|i| tree.insert(*i));
+        let lambda = tree.to_lambda(&mut self.rng, self.freevar_p, self.max_free_vars);
+        match self.std {
+            Standardization::Postfix => BTreeGen::postfix_standardize(lambda),
+            Standardization::Prefix => close_term(lambda),
+            Standardization::None => lambda,
+        }
+    }
+}
+
 impl Default for BTreeGen {
     fn default() -> Self {
         Self::new()
@@ -97,47 +181,46 @@ impl BTreeGen {
         let seed = cfg.seed.get();
         let rng = ChaCha8Rng::from_seed(seed);
         BTreeGen {
-            n: cfg.size,
+            size: cfg.size.clone(),
             freevar_p: cfg.freevar_generation_probability,
             max_free_vars: cfg.n_max_free_vars,
             std: cfg.standardization,
+            max_depth: cfg.max_depth,
 
             seed,
             rng,
         }
     }
 
-    pub fn generate(&mut self) -> Term {
-        let n = self.n;
-        assert!(
-            n > 0,
-            "btree generator does not produce zero-sized expressions."
-        );
-        let mut permutation = (0..n).collect::<Vec<u32>>();
-        permutation.shuffle(&mut self.rng);
-        let mut tree = BTree::new(permutation[0]);
-        permutation.iter().skip(1).for_each(|i| tree.insert(*i));
-        let lambda = tree.to_lambda(&mut self.rng, self.freevar_p, self.max_free_vars);
-        match self.std {
-            Standardization::Postfix => BTreeGen::postfix_standardize(lambda),
-            Standardization::Prefix => BTreeGen::prefix_standardize(lambda),
-            Standardization::None => lambda,
-        }
+    /// Snapshot the position of the generator's RNG. This is the word position within the
+    /// ChaCha8 stream keyed by `seed()`, so it only means something to a generator built from
+    /// the same seed and configuration.
+    pub fn rng_state(&self) -> u128 {
+        self.rng.get_word_pos()
     }
 
-    pub fn generate_n(&mut self, n: usize) -> Vec<Term> {
-        let mut v = Vec::with_capacity(n);
-        for _ in 0..n {
-            v.push(self.generate())
-        }
-        v
+    /// Fast-forward (or rewind) the RNG to a position previously returned by `rng_state`.
+    /// Subsequent calls to `generate` continue the stream from that point.
+    pub fn set_rng_state(&mut self, state: u128) {
+        self.rng.set_word_pos(state)
     }
 
-    pub fn seed(&self) -> [u8; 32] {
-        self.seed
+    /// Draw the size of the next tree from the configured distribution. Fixed sizes don't touch
+    /// the RNG, so they produce the same stream as before size distributions existed.
+    fn sample_size(&mut self) -> u32 {
+        match &self.size {
+            config::SizeSpec::Fixed(n) => *n,
+            config::SizeSpec::Uniform(min, max) => self.rng.gen_range(*min..=*max),
+            config::SizeSpec::Weighted(weights) => {
+                weights
+                    .choose_weighted(&mut self.rng, |(_, w)| *w)
+                    .expect("btree size weights are checked when the config is built")
+                    .0
+            }
+        }
     }
 
-    fn postfix_standardize(mut t: Term) -> Term {
+    pub(crate) fn postfix_standardize(mut t: Term) -> Term {
         let mut depth = 0;
         while t.has_free_variables() {
             t = Abs(Box::new(t));
@@ -150,16 +233,6 @@ impl BTreeGen {
         }
         t
     }
-
-    /// Add abstractions until the expression has no free variables
-    fn prefix_standardize(mut t: Term) -> Term {
-        // This is horrible, and can easily be made more efficient. Fortunaltely,
-        // lambda-expression generation is a one-off thing!
-        while t.has_free_variables() {
-            t = Abs(Box::new(t))
-        }
-        t
-    }
 }
 
 pub struct FontanaGen {
@@ -179,8 +252,15 @@ pub struct FontanaGen {
     rng: ChaCha8Rng,
 
     std: Standardization,
+
+    /// When set, the most terms of any one size to accept before rejecting that size
+    target_per_size: Option<usize>,
+    size_counts: HashMap<usize, usize>,
 }
 
+/// How many candidates `FontanaGen` draws for one term before giving up on flattening.
+const MAX_FLATTEN_RETRIES: usize = 1000;
+
 impl FontanaGen {
     pub fn new(
         min_depth: u32,
@@ -216,13 +296,15 @@ impl FontanaGen {
             seed,
             rng: ChaCha8Rng::from_seed(seed),
             std: Standardization::Prefix,
+            target_per_size: None,
+            size_counts: HashMap::new(),
         }
     }
 
     pub fn from_config(cfg: &config::FontanaGen) -> FontanaGen {
         let seed = cfg.seed.get();
 
-        FontanaGen::new(
+        let mut gen = FontanaGen::new(
             cfg.min_depth,
             cfg.max_depth,
             (
@@ -236,30 +318,67 @@ impl FontanaGen {
             cfg.free_variable_probability as f32,
             cfg.n_max_free_vars,
             seed,
-        )
+        );
+        if !cfg.close_terms {
+            gen.std = Standardization::None;
+        }
+        if cfg.flatten_sizes {
+            gen.flatten_sizes(cfg.target_per_size);
+        }
+        gen
+    }
+
+    /// Flatten the distribution over term sizes by rejection sampling: once `target_per_size`
+    /// terms of some size have been generated, further terms of that size are rejected. If
+    /// `MAX_FLATTEN_RETRIES` candidates in a row are rejected, every size reachable in practice
+    /// is full, so the counts start over and the least represented candidate is kept.
+    pub fn flatten_sizes(&mut self, target_per_size: usize) {
+        self.target_per_size = Some(target_per_size.max(1));
+        self.size_counts.clear();
     }
 
-    pub fn generate(&mut self) -> Term {
-        // <-- not Option<Term>
+    fn generate_unflattened(&mut self) -> Term {
         let lambda = self.rand_lambda(0, self.abs_prob.0, self.app_prob.0);
 
         match self.std {
-            Standardization::Prefix => Self::prefix_standardize(lambda),
-            Standardization::Postfix => Self::postfix_standardize(lambda),
+            Standardization::Prefix => close_term(lambda),
+            Standardization::Postfix => BTreeGen::postfix_standardize(lambda),
             Standardization::None => lambda,
         }
     }
 
-    pub fn generate_n(&mut self, n: usize) -> Vec<Term> {
-        let mut v = Vec::with_capacity(n);
-        for _ in 0..n {
-            v.push(self.generate())
+    fn generate_flattened(&mut self, target_per_size: usize) -> Term {
+        let mut fallback: Option<(usize, Term)> = None;
+        for _ in 0..MAX_FLATTEN_RETRIES {
+            let candidate = self.generate_unflattened();
+            let size = candidate.size();
+            let count = self.size_counts.get(&size).copied().unwrap_or(0);
+            if count < target_per_size {
+                self.size_counts.insert(size, count + 1);
+                return candidate;
+            }
+            if fallback.as_ref().is_none_or(|(c, _)| count < *c) {
+                fallback = Some((count, candidate));
+            }
         }
-        v
+
+        let (_, term) = fallback.expect("MAX_FLATTEN_RETRIES is positive");
+        self.size_counts.clear();
+        self.size_counts.insert(term.size(), 1);
+        term
     }
 
-    pub fn seed(&self) -> [u8; 32] {
-        self.seed
+    /// Snapshot the position of the generator's RNG. This is the word position within the
+    /// ChaCha8 stream keyed by `seed()`, so it only means something to a generator built from
+    /// the same seed and configuration.
+    pub fn rng_state(&self) -> u128 {
+        self.rng.get_word_pos()
+    }
+
+    /// Fast-forward (or rewind) the RNG to a position previously returned by `rng_state`.
+    /// Subsequent calls to `generate` continue the stream from that point.
+    pub fn set_rng_state(&mut self, state: u128) {
+        self.rng.set_word_pos(state)
     }
 
     // if depth of tree is not reached with a 50% probability either an abs or app is generated.
@@ -317,27 +436,241 @@ impl FontanaGen {
         };
         Term::Var(value)
     }
+}
 
-    fn prefix_standardize(mut t: Term) -> Term {
-        while t.has_free_variables() {
-            t = Term::Abs(Box::new(t))
+impl Generator for FontanaGen {
+    fn generate(&mut self) -> Term {
+        match self.target_per_size {
+            Some(target) => self.generate_flattened(target),
+            None => self.generate_unflattened(),
         }
-        t
     }
 
-    fn postfix_standardize(mut t: Term) -> Term {
-        let mut depth = 0;
-        while t.has_free_variables() {
-            t = Term::Abs(Box::new(t));
-            depth += 1;
+    fn seed(&self) -> [u8; 32] {
+        self.seed
+    }
+}
+
+/// Which terms a `RejectionGen` throws away and re-samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rejection {
+    /// Reject terms with free variables
+    pub free_variables: bool,
+
+    /// Reject terms isomorphic to the `I`, `K` or `S` combinators
+    pub combinators: bool,
+
+    /// Reject terms that don't take two arguments and use both of them
+    pub unused_arguments: bool,
+}
+
+impl Rejection {
+    /// Reject every kind of degenerate term.
+    pub fn all() -> Self {
+        Rejection {
+            free_variables: true,
+            combinators: true,
+            unused_arguments: true,
         }
+    }
 
-        for _ in 0..depth {
-            // Supply Identity (\x.x) for each bound variable
-            let identity = Term::Abs(Box::new(Term::Var(1)));
-            t = Term::App(Box::new((t, identity)));
+    /// Whether `t` passes all enabled checks.
+    pub fn accepts(&self, t: &Term) -> bool {
+        if self.free_variables && t.has_free_variables() {
+            return false;
         }
+        if self.combinators && [I(), K(), S()].iter().any(|c| t.is_isomorphic_to(c)) {
+            return false;
+        }
+        // `uses_both_arguments` expects at least two leading abstractions
+        if self.unused_arguments && !(has_two_args(t) && uses_both_arguments(t)) {
+            return false;
+        }
+        true
+    }
+}
 
+impl Default for Rejection {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// Wraps another generator and re-samples terms that `rejection` doesn't accept. After
+/// `max_retries` rejected re-samples, the last sample is returned anyway.
+pub struct RejectionGen<G> {
+    inner: G,
+    rejection: Rejection,
+    max_retries: usize,
+}
+
+impl<G: Generator> RejectionGen<G> {
+    pub fn new(inner: G, rejection: Rejection, max_retries: usize) -> Self {
+        RejectionGen {
+            inner,
+            rejection,
+            max_retries,
+        }
+    }
+
+    /// Get the wrapped generator.
+    pub fn into_inner(self) -> G {
+        self.inner
+    }
+}
+
+impl<G: Generator> Generator for RejectionGen<G> {
+    fn generate(&mut self) -> Term {
+        let mut t = self.inner.generate();
+        for _ in 0..self.max_retries {
+            if self.rejection.accepts(&t) {
+                break;
+            }
+            t = self.inner.generate();
+        }
         t
     }
+
+    fn seed(&self) -> [u8; 32] {
+        self.inner.seed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        combinators::{I, K, S},
+        parse,
+        term::Notation::Classic,
+        Term,
+    };
+
+    use super::{BTreeGen, FontanaGen, Generator, Rejection, RejectionGen};
+    use crate::config::{self, ConfigSeed, GenConfig};
+    use crate::lambda::term_depth;
+
+    /// Yields `terms` in a cycle, counting how many it has yielded.
+    struct Cycle {
+        terms: Vec<Term>,
+        n_generated: usize,
+    }
+
+    impl Generator for Cycle {
+        fn generate(&mut self) -> Term {
+            self.n_generated += 1;
+            self.terms[(self.n_generated - 1) % self.terms.len()].clone()
+        }
+
+        fn seed(&self) -> [u8; 32] {
+            [0; 32]
+        }
+    }
+
+    #[test]
+    fn rejection_resamples_degenerate_terms() {
+        let good = parse(r"\x.\y.y x", Classic).unwrap();
+        let cycle = Cycle {
+            terms: vec![K(), I(), good.clone(), S()],
+            n_generated: 0,
+        };
+        let mut gen = RejectionGen::new(cycle, Rejection::all(), 10);
+        let terms = gen.generate_n(5);
+        assert!(terms.iter().all(|t| *t == good));
+        assert!(terms.iter().all(|t| Rejection::all().accepts(t)));
+
+        // Nothing is ever accepted, so each term gives up after its retries
+        let only_k = Cycle {
+            terms: vec![K()],
+            n_generated: 0,
+        };
+        let mut gen = RejectionGen::new(only_k, Rejection::all(), 3);
+        assert_eq!(gen.generate_n(2), [K(), K()]);
+        assert_eq!(gen.into_inner().n_generated, 8);
+    }
+
+    #[test]
+    fn restored_rng_state_reproduces_stream() {
+        let mut btree = BTreeGen::from_config(&config::BTreeGen {
+            seed: ConfigSeed::new([7; 32]),
+            ..config::BTreeGen::new()
+        });
+        let mut fontana = FontanaGen::from_config(&config::FontanaGen {
+            seed: ConfigSeed::new([7; 32]),
+            ..config::FontanaGen::new()
+        });
+
+        btree.generate_n(17);
+        let state = btree.rng_state();
+        let tail = btree.generate_n(5);
+        btree.set_rng_state(state);
+        assert_eq!(btree.generate_n(5), tail);
+
+        fontana.generate_n(17);
+        let state = fontana.rng_state();
+        let tail = fontana.generate_n(5);
+        fontana.set_rng_state(state);
+        assert_eq!(fontana.generate_n(5), tail);
+    }
+
+    #[test]
+    fn btree_depth_is_bounded() {
+        let cfg = config::BTreeGen {
+            seed: ConfigSeed::new([4; 32]),
+            ..config::BTreeGen::new()
+        };
+        let natural = BTreeGen::from_config(&cfg).generate_n(50);
+        let max_natural = natural.iter().map(term_depth).max().unwrap();
+        let bound = max_natural - 2;
+
+        let bounded = BTreeGen::from_config(&config::BTreeGen {
+            max_depth: Some(bound),
+            ..cfg
+        })
+        .generate_n(50);
+        assert!(bounded.iter().all(|t| term_depth(t) <= bound));
+    }
+
+    #[test]
+    fn flattened_sizes_spread_evenly() {
+        let cfg = config::FontanaGen {
+            seed: ConfigSeed::new([3; 32]),
+            max_depth: 6,
+            ..config::FontanaGen::new()
+        };
+        let max_bucket = |terms: Vec<lambda_calculus::Term>| {
+            let mut counts = std::collections::HashMap::<usize, usize>::new();
+            for t in terms {
+                *counts.entry(t.size()).or_default() += 1;
+            }
+            counts.into_values().max().unwrap()
+        };
+
+        let natural = FontanaGen::from_config(&cfg).generate_n(100);
+        let flattened = FontanaGen::from_config(&config::FontanaGen {
+            flatten_sizes: true,
+            target_per_size: 5,
+            ..cfg
+        })
+        .generate_n(100);
+        assert!(max_bucket(natural) > 5);
+        assert!(max_bucket(flattened) <= 5);
+    }
+
+    #[test]
+    fn closed_fontana_terms_have_no_free_variables() {
+        let cfg = config::FontanaGen {
+            seed: ConfigSeed::new([5; 32]),
+            free_variable_probability: 0.5,
+            ..config::FontanaGen::new()
+        };
+        let terms = FontanaGen::from_config(&cfg).generate_n(200);
+        assert!(terms.iter().all(|t| !t.has_free_variables()));
+
+        let open = FontanaGen::from_config(&config::FontanaGen {
+            close_terms: false,
+            ..cfg
+        })
+        .generate_n(200);
+        assert!(open.iter().any(|t| t.has_free_variables()));
+    }
 }
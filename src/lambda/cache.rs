@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lambda_calculus::Term;
+
+use crate::config::ReductionOrder;
+
+use super::canonical::{canonical_key, CanonicalKey};
+use super::recursive::{reduce_bounded, LambdaCollisionError};
+
+/// The order and limits a reduction ran under. The same term can reach normal form under one
+/// set of limits and fail under another, so they are part of the key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Limits {
+    order: ReductionOrder,
+    rlimit: usize,
+    slimit: usize,
+    dlimit: Option<usize>,
+}
+
+type Reduction = Result<(Term, usize), LambdaCollisionError>;
+
+/// A cache of whole bounded reductions, from a term to its normal form and the number of steps
+/// taken, or to the error the reduction failed with. Keys are canonical forms, so
+/// alpha-equivalent terms share an entry.
+///
+/// Clones share their entries, so one cache can serve every soup of an experiment, across
+/// threads. Entries are never evicted: memory grows with the number of distinct compositions
+/// reduced, which is bounded by the number of reactions but not by the population. Reductions
+/// that time out are not cached, since they may succeed on a less loaded machine.
+#[derive(Debug, Clone, Default)]
+pub struct NormalFormCache {
+    entries: Arc<Mutex<HashMap<(CanonicalKey, Limits), Reduction>>>,
+}
+
+impl NormalFormCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The normal form of `t` within `rlimit` steps and size `slimit`, as `reduce_with_limit`
+    /// would leave it, with `LambdaCollisionError::ExceedsReductionLimit` if it is not reached
+    /// in fewer than `rlimit` steps. Reduces `t` only if no equivalent term has been reduced
+    /// under the same limits before.
+    pub fn reduce_cached(
+        &self,
+        t: &Term,
+        rlimit: usize,
+        slimit: usize,
+    ) -> Result<Term, LambdaCollisionError> {
+        self.reduce_counted(t, ReductionOrder::Hap, rlimit, slimit, None, None)
+            .map(|(expr, _)| expr)
+    }
+
+    /// Like `reduce_cached`, but with the order, depth limit and timeout of `reduce_bounded`,
+    /// and also returning the number of steps the reduction took.
+    pub(crate) fn reduce_counted(
+        &self,
+        t: &Term,
+        order: ReductionOrder,
+        rlimit: usize,
+        slimit: usize,
+        dlimit: Option<usize>,
+        timeout: Option<Duration>,
+    ) -> Reduction {
+        let limits = Limits {
+            order,
+            rlimit,
+            slimit,
+            dlimit,
+        };
+        let key = (canonical_key(t), limits);
+        if let Some(reduction) = self.entries.lock().unwrap().get(&key) {
+            return reduction.clone();
+        }
+
+        // Reduce without holding the lock, so other soups can use the cache meanwhile
+        let mut expr = t.clone();
+        let reduction = reduce_bounded(&mut expr, order.into(), rlimit, slimit, dlimit, timeout)
+            .and_then(|outcome| outcome.into_result())
+            .map(|n| (expr, n));
+        if reduction != Err(LambdaCollisionError::Timeout) {
+            self.entries.lock().unwrap().insert(key, reduction.clone());
+        }
+        reduction
+    }
+
+    /// Number of cached reductions.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget every cached reduction, in this cache and all its clones.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear()
+    }
+}
+
+/// Caches are equal if they share their entries.
+impl PartialEq for NormalFormCache {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.entries, &other.entries)
+    }
+}
+
+impl Eq for NormalFormCache {}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        app,
+        combinators::{I, K, S},
+    };
+
+    use crate::config::{self, ConfigSeed};
+    use crate::lambda::recursive::{reduce_with_limit, LambdaCollisionError, LambdaSoup};
+
+    use super::NormalFormCache;
+
+    #[test]
+    fn cached_reductions_match_fresh_ones() {
+        let cache = NormalFormCache::new();
+        let skk = app!(S(), K(), K());
+        let mut fresh = skk.clone();
+        assert!(reduce_with_limit(&mut fresh, 100, 100).is_normalized());
+        assert_eq!(cache.reduce_cached(&skk, 100, 100), Ok(fresh.clone()));
+        assert_eq!(cache.clone().reduce_cached(&skk, 100, 100), Ok(fresh));
+        assert_eq!(cache.len(), 1);
+
+        // Too few steps to reach normal form
+        assert_eq!(
+            cache.reduce_cached(&skk, 1, 100),
+            Err(LambdaCollisionError::ExceedsReductionLimit)
+        );
+        assert_eq!(cache.len(), 2);
+
+        let cfg = config::Reactor {
+            seed: ConfigSeed::new([9; 32]),
+            ..config::Reactor::new()
+        };
+        let terms = [S(), K(), I()].into_iter().cycle().take(30);
+        let mut plain = LambdaSoup::from_terms(&cfg, terms.clone());
+        let mut cached = LambdaSoup::from_terms(&cfg, terms);
+        cached.use_normal_form_cache(cache.clone());
+        plain.simulate_for(300, false);
+        cached.simulate_for(300, false);
+        assert!(plain.expressions().eq(cached.expressions()));
+        assert!(cache.len() > 2);
+    }
+}
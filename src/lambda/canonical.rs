@@ -0,0 +1,154 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+use lambda_calculus::Term;
+
+use super::eta_normalize;
+
+/// The identity of a lambda expression as a species. Two terms share a `canonical_key` iff they
+/// are alpha-equivalent, and an `eta_canonical_key` iff they are alpha-eta-equivalent.
+///
+/// `Term`s are stored with De Bruijn indices, so the key is the term itself; wrapping it keeps
+/// every consumer on the same notion of identity should that ever change. A key borrows as a
+/// `Term`, so maps keyed on `CanonicalKey` can be queried with a plain `&Term`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CanonicalKey(Term);
+
+impl CanonicalKey {
+    /// Whether `expr` has this key, without building a key for it.
+    pub fn matches(&self, expr: &Term) -> bool {
+        self.0 == *expr
+    }
+
+    /// A representative term for this key.
+    pub fn as_term(&self) -> &Term {
+        &self.0
+    }
+
+    pub fn into_term(self) -> Term {
+        self.0
+    }
+}
+
+impl Borrow<Term> for CanonicalKey {
+    fn borrow(&self) -> &Term {
+        &self.0
+    }
+}
+
+/// Get the canonical key of `expr`.
+pub fn canonical_key(expr: &Term) -> CanonicalKey {
+    CanonicalKey(expr.clone())
+}
+
+/// Get the key of `expr` as a species up to eta-equivalence as well, so `\x. f x` and `f`
+/// share a key. Keys from this and `canonical_key` must not be mixed, and `matches` only
+/// recognises terms already in eta-normal form.
+pub fn eta_canonical_key(expr: &Term) -> CanonicalKey {
+    CanonicalKey(eta_normalize(expr.clone()))
+}
+
+/// A fixed set of target terms, such as the combinators an experiment watches for, that
+/// answers whether a term is of the same species as one of them with a single hash lookup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermSet {
+    /// The first target with each key
+    first: HashMap<CanonicalKey, usize>,
+    /// For each target, the first target with the same key
+    aliases: Vec<usize>,
+    use_eta: bool,
+}
+
+impl TermSet {
+    /// Index `targets` by `canonical_key`.
+    pub fn new<'a>(targets: impl IntoIterator<Item = &'a Term>) -> Self {
+        Self::build(targets, false)
+    }
+
+    /// Index `targets` by `eta_canonical_key`, so eta-equivalent terms also match.
+    pub fn with_eta<'a>(targets: impl IntoIterator<Item = &'a Term>) -> Self {
+        Self::build(targets, true)
+    }
+
+    fn build<'a>(targets: impl IntoIterator<Item = &'a Term>, use_eta: bool) -> Self {
+        let mut set = TermSet {
+            first: HashMap::new(),
+            aliases: Vec::new(),
+            use_eta,
+        };
+        for (i, target) in targets.into_iter().enumerate() {
+            let first = *set.first.entry(set.key(target)).or_insert(i);
+            set.aliases.push(first);
+        }
+        set
+    }
+
+    fn key(&self, expr: &Term) -> CanonicalKey {
+        match self.use_eta {
+            true => eta_canonical_key(expr),
+            false => canonical_key(expr),
+        }
+    }
+
+    /// The index of the first target of the same species as `expr`, if any.
+    pub fn which(&self, expr: &Term) -> Option<usize> {
+        match self.use_eta {
+            true => self.first.get(&eta_canonical_key(expr)).copied(),
+            false => self.first.get(expr).copied(),
+        }
+    }
+
+    pub fn contains(&self, expr: &Term) -> bool {
+        self.which(expr).is_some()
+    }
+
+    /// Count the terms of `exprs` of each target's species, in target order. Targets of the
+    /// same species get the same count.
+    pub fn count<'a>(&self, exprs: impl IntoIterator<Item = &'a Term>) -> Vec<usize> {
+        self.count_copies(exprs.into_iter().map(|e| (e, 1)))
+    }
+
+    /// Like `count`, but for terms given with their number of copies.
+    pub fn count_copies<'a>(&self, exprs: impl IntoIterator<Item = (&'a Term, u32)>) -> Vec<usize> {
+        let mut counts = vec![0; self.len()];
+        for (e, copies) in exprs {
+            if let Some(i) = self.which(e) {
+                counts[i] += copies as usize;
+            }
+        }
+        self.aliases.iter().map(|&i| counts[i]).collect()
+    }
+
+    /// Number of targets, including repeats.
+    pub fn len(&self) -> usize {
+        self.aliases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        combinators::{I, K, S},
+        parse,
+        term::Notation::Classic,
+    };
+
+    use super::TermSet;
+
+    #[test]
+    fn term_set_finds_first_target() {
+        let apply = parse(r"\f.\x.f x", Classic).unwrap();
+        let set = TermSet::new(&[K(), S(), K()]);
+        assert_eq!(set.which(&parse(r"\a.\b.a", Classic).unwrap()), Some(0));
+        assert_eq!(set.which(&S()), Some(1));
+        assert!(!set.contains(&I()));
+        assert_eq!(set.count(&[K(), I(), S(), K()]), [2, 1, 2]);
+
+        assert!(!TermSet::new(&[I()]).contains(&apply));
+        assert_eq!(TermSet::with_eta(&[I()]).which(&apply), Some(0));
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, ConfigSeed};
+
+use super::pretty::{parse_pretty, pretty};
+use super::recursive::{LambdaParticle, LambdaSoup};
+
+/// Everything needed to resume a `LambdaSoup` exactly where it left off, in a form that
+/// serializes to readable JSON: the reactor the soup was built from, with its rules as source
+/// strings, every particle in order, and the position of the random number generator.
+///
+/// The outcome of the last reaction is not kept, so with the `osc` feature a restored soup's
+/// `last_reaction` is `None`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SoupState {
+    /// The reactor the soup was built from. Its seed is the seed of the soup's generator.
+    pub reactor: config::Reactor,
+    pub particles: Vec<ParticleState>,
+    /// `n_collisions` at the time each particle was inserted, aligned with `particles`.
+    pub births: Vec<usize>,
+    pub n_collisions: usize,
+    pub n_attempts: usize,
+    pub rng_stream: u64,
+    pub rng_word_pos: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParticleState {
+    /// The particle's expression, as rendered by `pretty`.
+    pub expr: String,
+    /// Whether this is a test particle.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub recursive: bool,
+}
+
+impl SoupState {
+    pub fn of(soup: &LambdaSoup) -> Self {
+        let reactor = config::Reactor {
+            maintain_constant_population_size: soup.maintain_constant_population_size,
+            discard_parents: soup.discard_parents,
+            max_species: soup.max_species,
+            max_population: soup.max_population,
+            seed: ConfigSeed::new(soup.rng.get_seed()),
+            ..soup.collider.to_config()
+        };
+        SoupState {
+            reactor,
+            particles: soup
+                .expressions
+                .iter()
+                .map(|p| ParticleState {
+                    expr: pretty(&p.expr),
+                    recursive: p.recursive,
+                })
+                .collect(),
+            births: soup.births.clone(),
+            n_collisions: soup.n_collisions,
+            n_attempts: soup.n_attempts,
+            rng_stream: soup.rng.get_stream(),
+            rng_word_pos: soup.rng.get_word_pos(),
+        }
+    }
+
+    /// Rebuild the soup this state was taken from. Fails if a particle's expression does not
+    /// parse.
+    pub fn restore(&self) -> io::Result<LambdaSoup> {
+        let mut particles = Vec::with_capacity(self.particles.len());
+        for particle in &self.particles {
+            let expr = parse_pretty(&particle.expr).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("bad expression {:?}: {}", particle.expr, e),
+                )
+            })?;
+            particles.push(LambdaParticle {
+                expr,
+                recursive: particle.recursive,
+            });
+        }
+        let mut soup = LambdaSoup::from_config(&self.reactor);
+        soup.perturb(particles);
+        soup.births = self.births.clone();
+        soup.n_collisions = self.n_collisions;
+        soup.n_attempts = self.n_attempts;
+
+        let mut rng = ChaCha8Rng::from_seed(self.reactor.seed.get());
+        rng.set_stream(self.rng_stream);
+        rng.set_word_pos(self.rng_word_pos);
+        soup.rng = rng;
+        Ok(soup)
+    }
+}
+
+impl LambdaSoup {
+    /// Write the soup's `SoupState` to `path` as JSON, replacing any existing file. Together
+    /// with `restore_from_file`, this lets a long run be stopped and resumed without changing
+    /// its outcome.
+    pub fn checkpoint_to_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &SoupState::of(self))?;
+        writer.flush()
+    }
+
+    /// Read a soup written by `checkpoint_to_file`.
+    pub fn restore_from_file(path: impl AsRef<Path>) -> io::Result<LambdaSoup> {
+        let reader = BufReader::new(File::open(path)?);
+        let state: SoupState = serde_json::from_reader(reader)?;
+        state.restore()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        combinators::{I, K, S},
+        Term,
+    };
+
+    use crate::config::{self, ConfigSeed};
+    use crate::lambda::recursive::LambdaSoup;
+
+    use super::SoupState;
+
+    fn state_json(soup: &LambdaSoup) -> String {
+        serde_json::to_string(&SoupState::of(soup)).unwrap()
+    }
+
+    #[test]
+    fn checkpoint_round_trip_is_exact() {
+        let cfg = config::Reactor {
+            discard_free_variable_expressions: false,
+            seed: ConfigSeed::new([6; 32]),
+            ..config::Reactor::new()
+        };
+        let open = Term::App(Box::new((Term::Var(1), I())));
+        let mut soup =
+            LambdaSoup::from_terms(&cfg, [S(), K(), I(), open].into_iter().cycle().take(40));
+        soup.add_test_expressions([K()]);
+        soup.simulate_for(200, false);
+
+        let path = std::env::temp_dir().join(format!("soup-{}.json", std::process::id()));
+        soup.checkpoint_to_file(&path).unwrap();
+        let mut restored = LambdaSoup::restore_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(state_json(&restored), state_json(&soup));
+
+        soup.simulate_for(200, false);
+        restored.simulate_for(200, false);
+        assert_eq!(state_json(&restored), state_json(&soup));
+        assert!(soup.expressions().eq(restored.expressions()));
+    }
+}
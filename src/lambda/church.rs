@@ -0,0 +1,84 @@
+use lambda_calculus::Term;
+
+use super::recursive::{reduce_with_limit, ReductionOutcome};
+
+/// Reduction step limit used by `as_church_numeral`.
+pub const CHURCH_REDUCTION_LIMIT: usize = 1000;
+
+/// Size limit used by `as_church_numeral` while reducing.
+pub const CHURCH_SIZE_LIMIT: usize = 8000;
+
+/// If `expr` reduces to a Church numeral `\f.\x. f (f ... (f x))`, return its value. Reduction
+/// gives up after `CHURCH_REDUCTION_LIMIT` steps, in which case this returns `None`.
+pub fn as_church_numeral(expr: &Term) -> Option<u64> {
+    as_church_numeral_with_limit(expr, CHURCH_REDUCTION_LIMIT, CHURCH_SIZE_LIMIT)
+}
+
+/// Like `as_church_numeral`, with explicit reduction and size limits.
+pub fn as_church_numeral_with_limit(expr: &Term, rlimit: usize, slimit: usize) -> Option<u64> {
+    if let Some(n) = church_numeral_shape(expr) {
+        return Some(n);
+    }
+    let mut normal = expr.clone();
+    match reduce_with_limit(&mut normal, rlimit, slimit) {
+        ReductionOutcome::Normalized(_) => church_numeral_shape(&normal),
+        _ => None,
+    }
+}
+
+/// Whether `expr` reduces to a Church numeral within `CHURCH_REDUCTION_LIMIT` steps.
+pub fn is_church_numeral(expr: &Term) -> bool {
+    as_church_numeral(expr).is_some()
+}
+
+// Match `\f.\x. f^n x` without reducing
+fn church_numeral_shape(expr: &Term) -> Option<u64> {
+    let Term::Abs(ref f) = expr else {
+        return None;
+    };
+    let Term::Abs(ref body) = **f else {
+        return None;
+    };
+
+    let mut n = 0;
+    let mut body: &Term = body;
+    loop {
+        match body {
+            Term::Var(1) => return Some(n),
+            Term::App(ref boxed) => {
+                let (ref left, ref right) = **boxed;
+                if *left != Term::Var(2) {
+                    return None;
+                }
+                n += 1;
+                body = right;
+            }
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        app,
+        data::num::church::{add, succ},
+        IntoChurchNum,
+    };
+
+    use super::{as_church_numeral, is_church_numeral};
+
+    #[test]
+    fn recognizes_numerals() {
+        for n in 0..10 {
+            assert_eq!(as_church_numeral(&n.into_church()), Some(n as u64));
+        }
+        assert!(!is_church_numeral(&succ()));
+    }
+
+    #[test]
+    fn reduces_before_matching() {
+        let sum = app!(add(), 3.into_church(), 4.into_church());
+        assert_eq!(as_church_numeral(&sum), Some(7));
+    }
+}
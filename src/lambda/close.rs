@@ -0,0 +1,30 @@
+use lambda_calculus::Term::{self, Abs};
+
+/// Close `expr` by wrapping it in abstractions until it has no free variables. A closed term is
+/// returned unchanged, and otherwise the term gains as many binders as its largest free De Bruijn
+/// index, which can exceed its number of distinct free variables: `Var(3)` alone gains three.
+pub fn close_term(mut expr: Term) -> Term {
+    // This is horrible, and can easily be made more efficient. Fortunately, it's only used
+    // for one-off preprocessing like lambda-expression generation.
+    while expr.has_free_variables() {
+        expr = Abs(Box::new(expr))
+    }
+    expr
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{abs, app, combinators::K, Term::Var};
+
+    use super::close_term;
+
+    #[test]
+    fn binders_follow_largest_free_index() {
+        assert_eq!(close_term(K()), K());
+        assert_eq!(close_term(Var(3)), abs(abs(abs(Var(3)))));
+        assert_eq!(
+            close_term(app(Var(1), Var(3))),
+            abs(abs(abs(app(Var(1), Var(3)))))
+        );
+    }
+}
@@ -3,17 +3,18 @@ use std::fmt::{Debug, Display};
 use std::marker::PhantomData;
 
 use crate::config;
+use crate::lambda::{canonical_key, CanonicalKey};
 use crate::supercollider::{Collider, Particle, Residue, Soup};
 use lambda_calculus::Term::Var;
 use lambda_calculus::{abs, app, Term};
 
-use rand::{Rng, SeedableRng};
+use rand::SeedableRng;
 use rand_chacha::ChaCha8Rng;
 
 pub type LambdaSoup =
     Soup<LambdaParticle, AlchemyCollider, LambdaCollisionOk, LambdaCollisionError>;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct LambdaParticle {
     pub expr: Term,
 }
@@ -24,7 +25,7 @@ pub struct AlchemyCollider {
     slimit: usize,
     disallow_recursive: bool,
     reaction_rules: Vec<Term>,
-    discard_copy_actions: bool,
+    discard_copy_actions: config::CopyPolicy,
     discard_identity: bool,
     discard_free_variable_expressions: bool,
 }
@@ -51,7 +52,7 @@ pub enum LambdaCollisionError {
     IsIdentity,
     IsParent,
     HasFreeVariables,
-    ExceedsDepthLimit,
+    ExceedsSizeLimit,
     BadArgument,
 }
 
@@ -95,11 +96,10 @@ pub fn reduce_with_limit(
             break;
         }
 
-        // WARNING: This is EXTREMELY expensive. Calling max_depth is log(depth), and is done
-        // per reduction step. Remove when possible.
-        let depth = expr.size();
-        if depth > slimit {
-            return Err(LambdaCollisionError::ExceedsDepthLimit);
+        // WARNING: This is EXTREMELY expensive. Computing the size is linear in the size of the
+        // expression, and is done per reduction step. Remove when possible.
+        if expr.size() > slimit {
+            return Err(LambdaCollisionError::ExceedsSizeLimit);
         }
         n += 1;
     }
@@ -146,8 +146,12 @@ impl AlchemyCollider {
                 return Err(LambdaCollisionError::IsIdentity);
             }
 
-            let is_copy_action = expr.is_isomorphic_to(&lt) || expr.is_isomorphic_to(&rt);
-            if is_copy_action && self.discard_copy_actions {
+            let copies_left = expr.is_isomorphic_to(&lt);
+            let copies_right = expr.is_isomorphic_to(&rt);
+            if self
+                .discard_copy_actions
+                .discards(copies_left, copies_right)
+            {
                 return Err(LambdaCollisionError::IsParent);
             }
 
@@ -179,6 +183,16 @@ impl Particle for LambdaParticle {
     fn is_isomorphic_to(&self, other: &Self) -> bool {
         self.expr.is_isomorphic_to(&other.expr)
     }
+
+    fn size(&self) -> usize {
+        self.expr.size()
+    }
+
+    type Species = CanonicalKey;
+
+    fn species(&self) -> CanonicalKey {
+        canonical_key(&self.expr)
+    }
 }
 
 impl Collider<LambdaParticle, LambdaCollisionOk, LambdaCollisionError> for AlchemyCollider {
@@ -191,6 +205,10 @@ impl Collider<LambdaParticle, LambdaCollisionOk, LambdaCollisionError> for Alche
     ) -> Result<LambdaCollisionOk, LambdaCollisionError> {
         self.collide(left, right)
     }
+
+    fn not_enough_expressions(&self) -> LambdaCollisionError {
+        LambdaCollisionError::NotEnoughExpressions
+    }
 }
 
 impl Residue<LambdaParticle> for LambdaCollisionOk {
@@ -228,8 +246,8 @@ impl fmt::Display for LambdaCollisionError {
                 Display::fmt("collision result has free variables", f)
             }
 
-            LambdaCollisionError::ExceedsDepthLimit => {
-                Display::fmt("expression exceeds depth limit during reduction", f)
+            LambdaCollisionError::ExceedsSizeLimit => {
+                Display::fmt("expression exceeds size limit during reduction", f)
             }
             LambdaCollisionError::BadArgument => Display::fmt(
                 "argument is truth-like or doesn't use all of own arguments",
@@ -265,19 +283,25 @@ impl LambdaSoup {
         let rng = ChaCha8Rng::from_seed(seed);
         Self {
             expressions: Vec::new(),
+            counts: Default::default(),
+            births: Vec::new(),
             collider: AlchemyCollider::from_config(cfg),
             maintain_constant_population_size: cfg.maintain_constant_population_size,
             discard_parents: cfg.discard_parents,
+            max_species: cfg.max_species,
+            max_population: cfg.max_population,
             rng,
+            #[cfg(feature = "osc")]
+            last_reaction: None,
             n_collisions: 0,
+            n_attempts: 0,
             t: PhantomData,
             e: PhantomData,
         }
     }
 
     pub fn add_lambda_expressions(&mut self, expressions: impl IntoIterator<Item = Term>) {
-        self.expressions
-            .extend(expressions.into_iter().map(|t| LambdaParticle { expr: t }))
+        self.perturb(expressions.into_iter().map(|t| LambdaParticle { expr: t }))
     }
 
     pub fn perturb_lambda_expressions<I>(&mut self, nterms: usize, expressions: I)
@@ -286,10 +310,7 @@ impl LambdaSoup {
         <I as IntoIterator>::IntoIter: Clone,
     {
         if self.maintain_constant_population_size {
-            for _ in 0..nterms {
-                let k = self.rng.gen_range(0..self.expressions.len());
-                self.expressions.swap_remove(k);
-            }
+            self.remove_random(nterms);
         }
         self.add_lambda_expressions(expressions.into_iter().cycle().take(nterms))
     }
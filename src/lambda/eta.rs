@@ -0,0 +1,78 @@
+use lambda_calculus::Term::{self, Abs, App, Var};
+
+/// Eta-reduce `expr` everywhere, rewriting every `\x. f x` with `x` not free in `f` to `f`,
+/// until no such subterm remains. The result is the unique eta-normal form of `expr`.
+///
+/// This is a bottom-up traversal, but each eta-redex needs an occurrence check and a
+/// reindexing pass over its body, so the worst case is quadratic in the size of `expr`.
+pub fn eta_normalize(expr: Term) -> Term {
+    match expr {
+        Var(_) => expr,
+        App(boxed) => {
+            let (left, right) = *boxed;
+            App(Box::new((eta_normalize(left), eta_normalize(right))))
+        }
+        Abs(body) => match eta_normalize(*body) {
+            App(boxed) if boxed.1 == Var(1) && !has_var(&boxed.0, 1) => shift_down(boxed.0, 0),
+            body => Abs(Box::new(body)),
+        },
+    }
+}
+
+/// Whether `a` and `b` are equal up to alpha- and eta-conversion. Both terms are
+/// eta-normalized, with the cost described in `eta_normalize`.
+pub fn eta_isomorphic(a: &Term, b: &Term) -> bool {
+    a == b || eta_normalize(a.clone()) == eta_normalize(b.clone())
+}
+
+/// Whether the variable bound `i` binders above `expr` occurs in it.
+fn has_var(expr: &Term, i: usize) -> bool {
+    match expr {
+        Var(j) => *j == i,
+        Abs(ref body) => has_var(body, i + 1),
+        App(ref boxed) => has_var(&boxed.0, i) || has_var(&boxed.1, i),
+    }
+}
+
+/// Remove the binder just above `expr` (under `depth` more binders), which must not occur in
+/// it, by lowering the indices of the variables bound further out.
+fn shift_down(expr: Term, depth: usize) -> Term {
+    match expr {
+        Var(j) if j > depth => Var(j - 1),
+        Var(_) => expr,
+        Abs(body) => Abs(Box::new(shift_down(*body, depth + 1))),
+        App(boxed) => {
+            let (left, right) = *boxed;
+            App(Box::new((
+                shift_down(left, depth),
+                shift_down(right, depth),
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        combinators::{I, S},
+        parse,
+        term::Notation::Classic,
+    };
+
+    use super::{eta_isomorphic, eta_normalize};
+
+    #[test]
+    fn eta_redexes_collapse() {
+        let apply = parse(r"\f.\x.f x", Classic).unwrap();
+        assert_eq!(eta_normalize(apply.clone()), I());
+        assert!(eta_isomorphic(&apply, &I()));
+
+        // `x` is free in `x x`, so `\x. (x x) x` is not an eta-redex
+        let omega_ish = parse(r"\x.x x x", Classic).unwrap();
+        assert_eq!(eta_normalize(omega_ish.clone()), omega_ish);
+        assert_eq!(eta_normalize(S()), S());
+
+        let outer = parse(r"\y.\f.\x.y f x", Classic).unwrap();
+        assert_eq!(eta_normalize(outer), I());
+    }
+}
@@ -1,5 +1,32 @@
+pub mod cache;
+
+pub mod canonical;
+
+pub mod checkpoint;
+
+pub mod church;
+
+pub mod close;
+
 pub mod core;
 
+pub mod eta;
+
+pub mod mutation;
+
+pub mod pretty;
+
 pub mod recursive;
 
+pub mod stats;
+
+pub use cache::NormalFormCache;
+pub use canonical::{canonical_key, eta_canonical_key, CanonicalKey, TermSet};
+pub use church::{as_church_numeral, is_church_numeral};
+pub use close::close_term;
+pub use eta::{eta_isomorphic, eta_normalize};
+pub use mutation::mutate_subtree;
+pub use pretty::{parse_pretty, pretty};
+pub use stats::{arity, term_depth, TermStats};
+
 // pub mod extrinsic;
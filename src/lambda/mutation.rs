@@ -0,0 +1,246 @@
+use std::cell::RefCell;
+use std::marker::PhantomData;
+
+use lambda_calculus::Term;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::config::{self, ConfigSeed};
+use crate::generators::{BTreeGen, Generator};
+use crate::supercollider::{Collider, Soup};
+
+use super::recursive::{
+    reduce_with_limit, LambdaCollisionError, LambdaCollisionOk, LambdaParticle,
+};
+
+pub type MutationSoup =
+    Soup<LambdaParticle, MutationCollider, LambdaCollisionOk, LambdaCollisionError>;
+
+/// A unary chemistry of point mutations: each reaction draws a single particle, replaces one
+/// of its subterms, chosen uniformly, with a fresh expression from a generator, and reduces the
+/// result. The mutant is the only product, so with the parent returned to the soup a reaction
+/// is replication with variation.
+///
+/// Subterms are replaced with `mutate_subtree`, so free variables of a generated subterm stay
+/// free rather than being captured by the abstractions around the position it replaces;
+/// mutants that still have free variables after reduction are discarded.
+#[derive(Debug, Clone)]
+pub struct MutationCollider {
+    gen: RefCell<BTreeGen>,
+    rng: RefCell<ChaCha8Rng>,
+    rlimit: usize,
+    slimit: usize,
+}
+
+impl MutationCollider {
+    /// Mutate with subterms from `gen`, choosing mutation sites with `seed`. Reductions are
+    /// bounded by `cfg`'s `reduction_cutoff` and `size_cutoff`.
+    pub fn new(cfg: &config::Reactor, gen: BTreeGen, seed: ConfigSeed) -> Self {
+        Self {
+            gen: RefCell::new(gen),
+            rng: RefCell::new(ChaCha8Rng::from_seed(seed.get())),
+            rlimit: cfg.reduction_cutoff,
+            slimit: cfg.size_cutoff,
+        }
+    }
+
+    fn mutate(&self, parent: LambdaParticle) -> Result<LambdaCollisionOk, LambdaCollisionError> {
+        let parent_size = parent.expr.size();
+        let mut expr = mutate_subtree(&parent.expr, &mut self.rng.borrow_mut(), |_| {
+            self.gen.borrow_mut().generate()
+        });
+        let n = reduce_with_limit(&mut expr, self.rlimit, self.slimit).into_result()?;
+        if expr.has_free_variables() {
+            return Err(LambdaCollisionError::HasFreeVariables);
+        }
+
+        let size = expr.size();
+        Ok(LambdaCollisionOk {
+            results: vec![LambdaParticle::new(expr)],
+            reductions: vec![n],
+            sizes: vec![size],
+            rule_indices: Vec::new(),
+            escalated: 0,
+            left_size: parent_size,
+            right_size: 0,
+        })
+    }
+}
+
+/// Replace a uniformly chosen subterm of `expr` with `replacement(rng)`. Free variables of the
+/// replacement are read at the root of `expr`, so they are shifted past the binders above the
+/// chosen position: a free variable stays free, and refers to the same variable as it would
+/// at the root. A closed replacement is inserted unchanged.
+pub fn mutate_subtree(
+    expr: &Term,
+    rng: &mut ChaCha8Rng,
+    replacement: impl FnOnce(&mut ChaCha8Rng) -> Term,
+) -> Term {
+    let site = rng.gen_range(0..expr.size());
+    let mut mutant = expr.clone();
+    let (node, depth) = nth_subterm(&mut mutant, site);
+    *node = shift_free(replacement(rng), depth, 0);
+    mutant
+}
+
+/// The `n`th subterm of `expr` in pre-order, so `0` is `expr` itself, and the number of
+/// abstractions above it.
+fn nth_subterm(expr: &mut Term, mut n: usize) -> (&mut Term, usize) {
+    let mut node = expr;
+    let mut depth = 0;
+    while n > 0 {
+        n -= 1;
+        node = match node {
+            Term::Abs(body) => {
+                depth += 1;
+                body
+            }
+            Term::App(boxed) => {
+                let (left, right) = &mut **boxed;
+                let left_size = left.size();
+                if n < left_size {
+                    left
+                } else {
+                    n -= left_size;
+                    right
+                }
+            }
+            Term::Var(_) => unreachable!("subterm index out of range"),
+        };
+    }
+    (node, depth)
+}
+
+/// Raise the index of every variable of `expr` that is free under `depth` binders by `by`.
+fn shift_free(expr: Term, by: usize, depth: usize) -> Term {
+    match expr {
+        Term::Var(i) if i > depth => Term::Var(i + by),
+        Term::Var(_) => expr,
+        Term::Abs(body) => Term::Abs(Box::new(shift_free(*body, by, depth + 1))),
+        Term::App(boxed) => {
+            let (left, right) = *boxed;
+            Term::App(Box::new((
+                shift_free(left, by, depth),
+                shift_free(right, by, depth),
+            )))
+        }
+    }
+}
+
+impl Collider<LambdaParticle, LambdaCollisionOk, LambdaCollisionError> for MutationCollider {
+    /// Mutate `left`. `right` is ignored; `Soup::react` only draws one reactant, as given by
+    /// `arity`.
+    fn collide(
+        &self,
+        left: LambdaParticle,
+        _right: LambdaParticle,
+    ) -> Result<LambdaCollisionOk, LambdaCollisionError> {
+        self.mutate(left)
+    }
+
+    fn not_enough_expressions(&self) -> LambdaCollisionError {
+        LambdaCollisionError::NotEnoughExpressions
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn collide_all(
+        &self,
+        reactants: Vec<LambdaParticle>,
+    ) -> Result<LambdaCollisionOk, LambdaCollisionError> {
+        let parent = reactants.into_iter().next();
+        match parent {
+            Some(parent) => self.mutate(parent),
+            None => Err(LambdaCollisionError::NotEnoughExpressions),
+        }
+    }
+}
+
+impl MutationSoup {
+    /// Generate an empty soup of `collider`'s chemistry, taking the population options and seed
+    /// from `cfg`. The reaction options in `cfg` are ignored.
+    pub fn from_collider(cfg: &config::Reactor, collider: MutationCollider) -> Self {
+        Self {
+            expressions: Vec::new(),
+            counts: Default::default(),
+            births: Vec::new(),
+            collider,
+            maintain_constant_population_size: cfg.maintain_constant_population_size,
+            discard_parents: cfg.discard_parents,
+            max_species: cfg.max_species,
+            max_population: cfg.max_population,
+            rng: ChaCha8Rng::from_seed(cfg.seed.get()),
+            #[cfg(feature = "osc")]
+            last_reaction: None,
+            n_collisions: 0,
+            n_attempts: 0,
+            t: PhantomData,
+            e: PhantomData,
+        }
+    }
+
+    pub fn add_lambda_expressions(&mut self, expressions: impl IntoIterator<Item = Term>) {
+        self.perturb(expressions.into_iter().map(LambdaParticle::new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        combinators::{I, K, S},
+        parse,
+        term::Notation::Classic,
+        Term,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::config::{self, ConfigSeed};
+    use crate::generators::{BTreeGen, Standardization};
+
+    use super::{mutate_subtree, MutationCollider, MutationSoup};
+
+    #[test]
+    fn mutants_are_closed() {
+        let cfg = config::Reactor {
+            seed: ConfigSeed::new([2; 32]),
+            ..config::Reactor::new()
+        };
+        let gen = BTreeGen::from_config(&config::BTreeGen {
+            size: config::SizeSpec::Fixed(5),
+            freevar_generation_probability: 0.2,
+            standardization: Standardization::Prefix,
+            n_max_free_vars: 4,
+            max_depth: None,
+            seed: ConfigSeed::new([3; 32]),
+        });
+        let collider = MutationCollider::new(&cfg, gen, ConfigSeed::new([4; 32]));
+        let mut soup = MutationSoup::from_collider(&cfg, collider);
+        soup.add_lambda_expressions([S(), K(), I()].into_iter().cycle().take(30));
+
+        let n_successes = soup.simulate_for(100, false);
+        assert!(n_successes > 0);
+        assert_eq!(soup.len(), 30);
+        assert!(soup.expressions().all(|p| !p.expr.has_free_variables()));
+    }
+
+    #[test]
+    fn subtree_mutation_keeps_free_variables_free() {
+        let mut rng = ChaCha8Rng::from_seed([5; 32]);
+        let expr = parse(r"\x.\y.x (\z.z y)", Classic).unwrap();
+        let bind = |t: Term| Term::Abs(Box::new(t));
+        for _ in 0..100 {
+            let mutant = mutate_subtree(&expr, &mut rng, |_| K());
+            assert!(!mutant.has_free_variables());
+
+            // `\a.a free1` refers to the root's first free variable wherever it is inserted, so
+            // exactly one binder around the mutant closes it
+            let replacement = bind(Term::App(Box::new((Term::Var(1), Term::Var(2)))));
+            let mutant = mutate_subtree(&expr, &mut rng, |_| replacement);
+            assert!(mutant.has_free_variables());
+            assert!(!bind(mutant).has_free_variables());
+        }
+    }
+}
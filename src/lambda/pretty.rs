@@ -0,0 +1,120 @@
+use std::fmt::Write;
+
+use lambda_calculus::{parse, parser::ParseError, term::Notation::Classic, Term};
+
+const NAMES: &[u8] = b"xyzwvutsrqponmlkjihgfedcba";
+
+/// Render `expr` in classic notation with readable names: the outermost binder is `x`, the
+/// next `y`, then `z`, `w`, ... and `x1`, `y1`, ... once the alphabet runs out. Free variables
+/// are named `free1`, `free2`, ... by their De Bruijn index above the root. Closed terms
+/// round-trip through `lambda_calculus::parse` in classic notation.
+pub fn pretty(expr: &Term) -> String {
+    let mut out = String::new();
+    write_term(&mut out, expr, 0);
+    out
+}
+
+/// Parse the output of `pretty` back into exactly the term it was rendered from, free variables
+/// included: each `freeN` is read as the `N`th variable free at the root, however deeply it is
+/// nested.
+pub fn parse_pretty(input: &str) -> Result<Term, ParseError> {
+    let n_free = free_names(input).max().unwrap_or(0);
+    let binders: String = (1..=n_free).rev().map(|i| format!("\\free{i}.")).collect();
+    let mut expr = parse(&(binders + input), Classic)?;
+    for _ in 0..n_free {
+        expr = match expr {
+            Term::Abs(body) => *body,
+            _ => unreachable!("parse dropped a binder"),
+        };
+    }
+    Ok(expr)
+}
+
+/// The `N` of every `freeN` name in `input`.
+fn free_names(input: &str) -> impl Iterator<Item = usize> + '_ {
+    input.match_indices("free").filter_map(|(i, _)| {
+        let follows_name = input[..i]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let digits: String = input[i + 4..]
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        if follows_name {
+            None
+        } else {
+            digits.parse().ok()
+        }
+    })
+}
+
+fn name(binder: usize) -> String {
+    let letter = NAMES[binder % NAMES.len()] as char;
+    match binder / NAMES.len() {
+        0 => letter.to_string(),
+        n => format!("{letter}{n}"),
+    }
+}
+
+/// Write `expr` under `depth` enclosing abstractions. Abstractions extend as far right as
+/// possible and application associates to the left, so only abstractions on the left of an
+/// application and non-variables on its right need parentheses.
+fn write_term(out: &mut String, expr: &Term, depth: usize) {
+    match expr {
+        Term::Var(i) if *i > depth => write!(out, "free{}", i - depth).unwrap(),
+        Term::Var(i) => out.push_str(&name(depth - i)),
+        Term::Abs(ref body) => {
+            write!(out, "\\{}.", name(depth)).unwrap();
+            write_term(out, body, depth + 1);
+        }
+        Term::App(ref boxed) => {
+            let (ref left, ref right) = **boxed;
+            write_wrapped(out, left, depth, matches!(left, Term::Abs(_)));
+            out.push(' ');
+            write_wrapped(out, right, depth, !matches!(right, Term::Var(_)));
+        }
+    }
+}
+
+fn write_wrapped(out: &mut String, expr: &Term, depth: usize, parens: bool) {
+    if parens {
+        out.push('(');
+        write_term(out, expr, depth);
+        out.push(')');
+    } else {
+        write_term(out, expr, depth);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        combinators::{S, Y},
+        parse,
+        term::Notation::Classic,
+        Term::{Abs, App, Var},
+    };
+
+    use super::{parse_pretty, pretty};
+
+    #[test]
+    fn pretty_names_round_trip() {
+        assert_eq!(pretty(&S()), r"\x.\y.\z.x z (y z)");
+        for term in [S(), Y()] {
+            assert_eq!(parse(&pretty(&term), Classic).unwrap(), term);
+        }
+    }
+
+    #[test]
+    fn free_variables_round_trip() {
+        // \x. free2 (x free1)
+        let open = Abs(Box::new(App(Box::new((
+            Var(3),
+            App(Box::new((Var(1), Var(2)))),
+        )))));
+        assert_eq!(pretty(&open), r"\x.free2 (x free1)");
+        assert_eq!(parse_pretty(&pretty(&open)).unwrap(), open);
+        assert_eq!(parse_pretty(&pretty(&S())).unwrap(), S());
+    }
+}
@@ -1,42 +1,80 @@
 use core::fmt;
+use std::collections::HashSet;
 use std::fmt::{Debug, Display};
+use std::io::{self, Write};
 use std::marker::PhantomData;
+use std::time::{Duration, Instant};
 
-use crate::config;
+use crate::config::{self, ReductionOrder};
+use crate::generators::Generator;
+use crate::lambda::{
+    arity, canonical_key, eta_canonical_key, eta_isomorphic, pretty, term_depth, CanonicalKey,
+    NormalFormCache, TermSet,
+};
 use crate::supercollider::{Collider, Particle, Residue, Soup};
-use lambda_calculus::{abs, app, Term, Var};
+use lambda_calculus::{abs, app, reduction::Order, Term, Var, HAP};
 
-use rand::{Rng, SeedableRng};
+use rand::{seq::SliceRandom, SeedableRng};
 use rand_chacha::ChaCha8Rng;
+use serde_json::{json, Value};
 
 pub type LambdaSoup =
     Soup<LambdaParticle, AlchemyCollider, LambdaCollisionOk, LambdaCollisionError>;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct LambdaParticle {
     pub expr: Term,
-    recursive: bool,
+    pub(crate) recursive: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AlchemyCollider {
     rlimit: usize,
     slimit: usize,
+    dlimit: Option<usize>,
+    escalation: Option<(usize, usize)>,
+    timeout: Option<Duration>,
     disallow_recursive: bool,
     reaction_rules: Vec<Term>,
-    discard_copy_actions: bool,
+    reduction_order: ReductionOrder,
+    /// Overrides of `reduction_order`, index-aligned with `reaction_rules` but possibly shorter
+    rule_orders: Vec<Option<ReductionOrder>>,
+    discard_copy_actions: config::CopyPolicy,
     discard_identity: bool,
+    /// The rules as species, if products isomorphic to them are discarded
+    rule_isomorphs: Option<TermSet>,
+    catalysts: Vec<Term>,
+    /// `catalysts` as species
+    catalyst_set: TermSet,
     discard_free_variable_expressions: bool,
+    first_nontrivial: bool,
+    use_eta: bool,
+    test_reward: usize,
+    /// Shared cache of rule reductions, if any
+    cache: Option<NormalFormCache>,
 }
 
 /// The result of composing a vector `v` of 2-ary lambda expressions with
 /// the expressions A and B.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LambdaCollisionOk {
+    /// Products of the collision. For rule-based collisions, `results`, `reductions`, `sizes`
+    /// and `rule_indices` are index-aligned.
     pub results: Vec<LambdaParticle>,
+
+    /// Number of reduction steps taken to produce each result
     pub reductions: Vec<usize>,
+
+    /// Size of each result
     pub sizes: Vec<usize>,
 
+    /// Index into the reactor's `rules` of the rule that produced each result. Empty when the
+    /// left reactant is a test expression, since no rule is applied.
+    pub rule_indices: Vec<usize>,
+
+    /// Number of results that only reduced within the reactor's `escalation` limits
+    pub escalated: usize,
+
     /// Size of A
     pub left_size: usize,
 
@@ -44,19 +82,40 @@ pub struct LambdaCollisionOk {
     pub right_size: usize,
 }
 
+/// A product of `AlchemyCollider::dry_collide` that `collide` would have discarded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscardReason {
+    /// Index of the product in the dry collision's `results`
+    pub result: usize,
+
+    /// The error `collide` would have rejected the product with
+    pub reason: LambdaCollisionError,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LambdaCollisionError {
     ExceedsReductionLimit,
     NotEnoughExpressions,
     IsIdentity,
+    IsRule,
     IsParent,
     HasFreeVariables,
+    ExceedsSizeLimit,
     ExceedsDepthLimit,
     RecursiveArgument,
     BadArgument,
+    Timeout,
 }
 
 impl LambdaParticle {
+    /// Wrap `expr` as an ordinary (non-test) particle.
+    pub fn new(expr: Term) -> Self {
+        LambdaParticle {
+            expr,
+            recursive: false,
+        }
+    }
+
     pub fn get_underlying_term(&self) -> &Term {
         &self.expr
     }
@@ -66,13 +125,9 @@ impl LambdaParticle {
     }
 }
 
+/// Whether `expr` takes at least two arguments, i.e. has an `arity` of 2 or more.
 pub fn has_two_args(expr: &Term) -> bool {
-    if let Term::Abs(ref body) = expr {
-        if let Term::Abs(_) = **body {
-            return true;
-        }
-    }
-    false
+    arity(expr) >= 2
 }
 
 // Check if expr has the form \x1. ... \xn. var for n >= 2
@@ -107,42 +162,143 @@ pub fn uses_both_arguments(expr: &Term) -> bool {
     left && right
 }
 
-pub fn reduce_with_limit(
+/// How a bounded reduction ended. A reduction that runs out of steps at a bounded size is
+/// likely looping, while one that outgrows the size limit is exploding, and the two mean
+/// different things chemically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReductionOutcome {
+    /// Reached normal form after this many steps
+    Normalized(usize),
+
+    /// Took the full step limit, leaving an expression of this size. As everywhere else, a
+    /// reduction that reaches normal form on its last allowed step counts as hitting the limit.
+    HitStepLimit(usize),
+
+    /// Outgrew the size limit on this step
+    HitSizeLimit(usize),
+}
+
+impl ReductionOutcome {
+    pub fn is_normalized(&self) -> bool {
+        matches!(self, ReductionOutcome::Normalized(_))
+    }
+
+    /// The number of steps to normal form, or the collision error for the limit that was hit:
+    /// `ExceedsReductionLimit` or `ExceedsSizeLimit`.
+    pub fn into_result(self) -> Result<usize, LambdaCollisionError> {
+        match self {
+            ReductionOutcome::Normalized(n) => Ok(n),
+            ReductionOutcome::HitStepLimit(_) => Err(LambdaCollisionError::ExceedsReductionLimit),
+            ReductionOutcome::HitSizeLimit(_) => Err(LambdaCollisionError::ExceedsSizeLimit),
+        }
+    }
+}
+
+/// Reduce `expr` in place for at most `rlimit` steps, giving up once it is larger than
+/// `slimit`, and report which of these happened first.
+pub fn reduce_with_limit(expr: &mut Term, rlimit: usize, slimit: usize) -> ReductionOutcome {
+    reduce_bounded(expr, HAP, rlimit, slimit, None, None)
+        .expect("reductions without depth or time limits cannot fail")
+}
+
+/// Like `reduce_with_limit`, but reduce with `order` rather than `HAP`, and also give up with
+/// `LambdaCollisionError::ExceedsDepthLimit` once the expression is nested deeper than
+/// `dlimit`, and with `LambdaCollisionError::Timeout` once `timeout` has elapsed. The clock is
+/// checked after every step, which is cheap next to the size check.
+pub fn reduce_bounded(
     expr: &mut Term,
+    order: Order,
     rlimit: usize,
     slimit: usize,
-) -> Result<usize, LambdaCollisionError> {
-    let mut n = 0;
-    for _ in 0..rlimit {
-        if expr.reduce(lambda_calculus::HAP, 1) == 0 {
-            break;
+    dlimit: Option<usize>,
+    timeout: Option<Duration>,
+) -> Result<ReductionOutcome, LambdaCollisionError> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    for n in 0..rlimit {
+        if expr.reduce(order, 1) == 0 {
+            return Ok(ReductionOutcome::Normalized(n));
+        }
+
+        // WARNING: This is EXTREMELY expensive. Computing the size is linear in the size of the
+        // expression, and is done per reduction step. Remove when possible.
+        if expr.size() > slimit {
+            return Ok(ReductionOutcome::HitSizeLimit(n + 1));
+        }
+        if let Some(dlimit) = dlimit {
+            if term_depth(expr) > dlimit {
+                return Err(LambdaCollisionError::ExceedsDepthLimit);
+            }
         }
 
-        // WARNING: This is EXTREMELY expensive. Calling max_depth is log(depth), and is done
-        // per reduction step. Remove when possible.
-        let depth = expr.size();
-        if depth > slimit {
-            return Err(LambdaCollisionError::ExceedsDepthLimit);
+        if let Some(deadline) = deadline {
+            if Instant::now() > deadline {
+                return Err(LambdaCollisionError::Timeout);
+            }
         }
-        n += 1;
     }
-    Ok(n)
+    Ok(ReductionOutcome::HitStepLimit(expr.size()))
 }
 
 impl AlchemyCollider {
     pub fn from_config(cfg: &config::Reactor) -> Self {
+        let parse_all = |terms: &[String]| {
+            terms
+                .iter()
+                .map(|r| lambda_calculus::parse(r, lambda_calculus::Classic).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let species = |terms: &[Term]| match cfg.use_eta {
+            true => TermSet::with_eta(terms),
+            false => TermSet::new(terms),
+        };
+        let reaction_rules = parse_all(&cfg.rules);
+        let rule_isomorphs = cfg.discard_rule_isomorphs.then(|| species(&reaction_rules));
+        let catalysts = parse_all(&cfg.catalysts);
+        let catalyst_set = species(&catalysts);
         Self {
             rlimit: cfg.reduction_cutoff,
             slimit: cfg.size_cutoff,
+            dlimit: cfg.depth_cutoff,
+            escalation: cfg.escalation,
+            timeout: cfg.reduction_timeout_ms.map(Duration::from_millis),
             disallow_recursive: false,
-            reaction_rules: cfg
-                .rules
-                .iter()
-                .map(|r| lambda_calculus::parse(r, lambda_calculus::Classic).unwrap())
-                .collect(),
+            reaction_rules,
+            reduction_order: cfg.reduction_order,
+            rule_orders: cfg.rule_orders.clone(),
             discard_copy_actions: cfg.discard_copy_actions,
             discard_identity: cfg.discard_identity,
+            rule_isomorphs,
+            catalysts,
+            catalyst_set,
             discard_free_variable_expressions: cfg.discard_free_variable_expressions,
+            first_nontrivial: cfg.first_nontrivial_rule,
+            use_eta: cfg.use_eta,
+            test_reward: cfg.test_reward,
+            cache: None,
+        }
+    }
+
+    /// A reactor whose `from_config` builds this collider, with the rules rendered by
+    /// `pretty`. The population options and seed are those of `Reactor::new`.
+    pub fn to_config(&self) -> config::Reactor {
+        config::Reactor {
+            rules: self.reaction_rules.iter().map(pretty).collect(),
+            reduction_order: self.reduction_order,
+            rule_orders: self.rule_orders.clone(),
+            discard_copy_actions: self.discard_copy_actions,
+            discard_identity: self.discard_identity,
+            discard_rule_isomorphs: self.rule_isomorphs.is_some(),
+            catalysts: self.catalysts.iter().map(pretty).collect(),
+            discard_free_variable_expressions: self.discard_free_variable_expressions,
+            first_nontrivial_rule: self.first_nontrivial,
+            use_eta: self.use_eta,
+            test_reward: self.test_reward,
+            reduction_cutoff: self.rlimit,
+            size_cutoff: self.slimit,
+            depth_cutoff: self.dlimit,
+            escalation: self.escalation,
+            reduction_timeout_ms: self.timeout.map(|t| t.as_millis() as u64),
+            ..config::Reactor::new()
         }
     }
 
@@ -162,13 +318,23 @@ impl AlchemyCollider {
         let right_size = rt.size();
 
         let mut expr = app!(lt, rt.clone());
-        let n = reduce_with_limit(&mut expr, 32000, 16000)?;
+        // A test that runs out of steps still has an answer, judged on the term it reached
+        let outcome = reduce_bounded(&mut expr, HAP, 32000, 16000, self.dlimit, self.timeout)?;
+        let n = match outcome {
+            ReductionOutcome::Normalized(n) => n,
+            ReductionOutcome::HitStepLimit(_) => 32000,
+            ReductionOutcome::HitSizeLimit(_) => {
+                return Err(LambdaCollisionError::ExceedsSizeLimit)
+            }
+        };
 
         if expr.is_isomorphic_to(&lambda_calculus::data::boolean::tru()) {
             Ok(LambdaCollisionOk {
-                results: vec![right.clone(); 100],
+                results: vec![right.clone(); self.test_reward],
                 reductions: vec![n],
                 sizes: vec![expr.size()],
+                rule_indices: Vec::new(),
+                escalated: 0,
                 left_size,
                 right_size,
             })
@@ -177,6 +343,8 @@ impl AlchemyCollider {
                 results: vec![left],
                 reductions: vec![n],
                 sizes: vec![expr.size()],
+                rule_indices: Vec::new(),
+                escalated: 0,
                 left_size,
                 right_size,
             })
@@ -196,44 +364,209 @@ impl AlchemyCollider {
         }
         let mut collision_results = Vec::with_capacity(self.reaction_rules.len());
 
-        for rule in &self.reaction_rules {
-            let mut expr = app!(rule.clone(), lt.clone(), rt.clone());
-            let n = reduce_with_limit(&mut expr, self.rlimit, self.slimit)?;
-            let size = expr.size();
-
-            if n == self.rlimit {
-                return Err(LambdaCollisionError::ExceedsReductionLimit);
+        let mut last_error = None;
+        for i in 0..self.reaction_rules.len() {
+            let result = self.apply_rule(i, &lt, &rt);
+            if !self.first_nontrivial {
+                let (expr, n, size, escalated) = result?;
+                collision_results.push((expr, n, size, i, escalated));
+                continue;
             }
-
-            let identity = abs(Var(1));
-            if expr.is_isomorphic_to(&identity) && self.discard_identity {
-                return Err(LambdaCollisionError::IsIdentity);
-            }
-
-            let is_copy_action = expr.is_isomorphic_to(&lt) || expr.is_isomorphic_to(&rt);
-            if is_copy_action && self.discard_copy_actions {
-                return Err(LambdaCollisionError::IsParent);
+            match result {
+                Ok((expr, n, size, escalated)) => {
+                    collision_results.push((expr, n, size, i, escalated));
+                    break;
+                }
+                Err(e) => last_error = Some(e),
             }
-
-            if expr.has_free_variables() && self.discard_free_variable_expressions {
-                return Err(LambdaCollisionError::HasFreeVariables);
+        }
+        if collision_results.is_empty() {
+            if let Some(e) = last_error {
+                return Err(e);
             }
-
-            let expr = LambdaParticle {
-                expr,
-                recursive: false,
-            };
-
-            collision_results.push((expr, n, size))
         }
         Ok(LambdaCollisionOk {
             results: collision_results.iter().map(|t| t.0.clone()).collect(),
             reductions: collision_results.iter().map(|t| t.1).collect(),
             sizes: collision_results.iter().map(|t| t.2).collect(),
+            rule_indices: collision_results.iter().map(|t| t.3).collect(),
+            escalated: collision_results.iter().filter(|t| t.4).count(),
             left_size: lt.size(),
             right_size: rt.size(),
         })
     }
+
+    /// Reduce rule `i` applied to `lt` and `rt`, and check the product against the discard
+    /// filters. Returns the product with its reduction count, its size, and whether it needed
+    /// the escalated limits. Products are always in normal form: a reduction that hasn't
+    /// converged within `rlimit` steps fails with `ExceedsReductionLimit` rather than yielding
+    /// a partially-reduced term.
+    fn apply_rule(
+        &self,
+        i: usize,
+        lt: &Term,
+        rt: &Term,
+    ) -> Result<(LambdaParticle, usize, usize, bool), LambdaCollisionError> {
+        let (expr, n, size, escalated) = self.reduce_rule(i, lt, rt)?;
+        if let Some(reason) = self.discard_reason(&expr.expr, lt, rt) {
+            return Err(reason);
+        }
+        Ok((expr, n, size, escalated))
+    }
+
+    /// Reduce rule `i` applied to `lt` and `rt` with the rule's order, without applying the
+    /// discard filters. A reduction that exceeds the reduction limit is retried once with the
+    /// `escalation` limits, if set.
+    fn reduce_rule(
+        &self,
+        i: usize,
+        lt: &Term,
+        rt: &Term,
+    ) -> Result<(LambdaParticle, usize, usize, bool), LambdaCollisionError> {
+        let start = app!(self.reaction_rules[i].clone(), lt.clone(), rt.clone());
+        let order = self.rule_order(i);
+        let retry = self.escalation.map(|extra| (start.clone(), extra));
+        let result = self.reduce_within(start, order, self.rlimit, self.slimit);
+        let (expr, n, escalated) = match (result, retry) {
+            (Err(LambdaCollisionError::ExceedsReductionLimit), Some((start, extra))) => {
+                let (extra_r, extra_s) = extra;
+                let (rlimit, slimit) = (self.rlimit + extra_r, self.slimit + extra_s);
+                let (expr, n) = self.reduce_within(start, order, rlimit, slimit)?;
+                (expr, n, true)
+            }
+            (result, _) => {
+                let (expr, n) = result?;
+                (expr, n, false)
+            }
+        };
+        let size = expr.size();
+
+        let expr = LambdaParticle {
+            expr,
+            recursive: false,
+        };
+        Ok((expr, n, size, escalated))
+    }
+
+    /// The key of `expr` as a species: `canonical_key`, or `eta_canonical_key` if `use_eta`
+    /// is set.
+    fn species_key(&self, expr: &Term) -> CanonicalKey {
+        if self.use_eta {
+            eta_canonical_key(expr)
+        } else {
+            canonical_key(expr)
+        }
+    }
+
+    /// The order rule `i` is reduced with: its override, or the collider's order.
+    fn rule_order(&self, i: usize) -> ReductionOrder {
+        self.rule_orders
+            .get(i)
+            .copied()
+            .flatten()
+            .unwrap_or(self.reduction_order)
+    }
+
+    /// Reduce `expr` to normal form with `order` within `rlimit` steps and `slimit` size,
+    /// returning it with the number of steps taken.
+    fn reduce_within(
+        &self,
+        mut expr: Term,
+        order: ReductionOrder,
+        rlimit: usize,
+        slimit: usize,
+    ) -> Result<(Term, usize), LambdaCollisionError> {
+        if let Some(ref cache) = self.cache {
+            return cache.reduce_counted(&expr, order, rlimit, slimit, self.dlimit, self.timeout);
+        }
+        let (dlimit, timeout) = (self.dlimit, self.timeout);
+        let n = reduce_bounded(&mut expr, order.into(), rlimit, slimit, dlimit, timeout)?;
+        Ok((expr, n.into_result()?))
+    }
+
+    /// The error the discard filters reject the product `expr` of `lt` and `rt` with, if any.
+    /// With `use_eta`, the identity and copy checks hold up to eta-equivalence.
+    fn discard_reason(&self, expr: &Term, lt: &Term, rt: &Term) -> Option<LambdaCollisionError> {
+        let same = |a: &Term, b: &Term| match self.use_eta {
+            true => eta_isomorphic(a, b),
+            false => a.is_isomorphic_to(b),
+        };
+
+        let identity = abs(Var(1));
+        if same(expr, &identity) && self.discard_identity {
+            return Some(LambdaCollisionError::IsIdentity);
+        }
+
+        if let Some(ref rules) = self.rule_isomorphs {
+            if rules.contains(expr) {
+                return Some(LambdaCollisionError::IsRule);
+            }
+        }
+
+        let copies_left = same(expr, lt);
+        let copies_right = same(expr, rt);
+        if self
+            .discard_copy_actions
+            .discards(copies_left, copies_right)
+        {
+            return Some(LambdaCollisionError::IsParent);
+        }
+
+        if expr.has_free_variables() && self.discard_free_variable_expressions {
+            return Some(LambdaCollisionError::HasFreeVariables);
+        }
+        None
+    }
+
+    /// Collide `left` with `right` like `collide`, but keep the products the discard filters
+    /// would reject, and report which ones they are. Every rule is applied, even with
+    /// `first_nontrivial_rule` set, so this shows how much chemistry the filters throw away.
+    /// Reduction failures still fail the whole collision.
+    pub fn dry_collide(
+        &self,
+        left: LambdaParticle,
+        right: LambdaParticle,
+    ) -> (
+        Result<LambdaCollisionOk, LambdaCollisionError>,
+        Vec<DiscardReason>,
+    ) {
+        if left.recursive {
+            return (self.recursive_collide(left, right), Vec::new());
+        }
+        if right.recursive {
+            return (Err(LambdaCollisionError::RecursiveArgument), Vec::new());
+        }
+
+        let (lt, rt) = (left.expr, right.expr);
+        let mut residue = LambdaCollisionOk {
+            results: Vec::with_capacity(self.reaction_rules.len()),
+            reductions: Vec::with_capacity(self.reaction_rules.len()),
+            sizes: Vec::with_capacity(self.reaction_rules.len()),
+            rule_indices: Vec::with_capacity(self.reaction_rules.len()),
+            escalated: 0,
+            left_size: lt.size(),
+            right_size: rt.size(),
+        };
+        let mut discards = Vec::new();
+        for i in 0..self.reaction_rules.len() {
+            let (expr, n, size, escalated) = match self.reduce_rule(i, &lt, &rt) {
+                Ok(product) => product,
+                Err(e) => return (Err(e), discards),
+            };
+            if let Some(reason) = self.discard_reason(&expr.expr, &lt, &rt) {
+                discards.push(DiscardReason {
+                    result: residue.results.len(),
+                    reason,
+                });
+            }
+            residue.results.push(expr);
+            residue.reductions.push(n);
+            residue.sizes.push(size);
+            residue.rule_indices.push(i);
+            residue.escalated += escalated as usize;
+        }
+        (Ok(residue), discards)
+    }
 }
 
 impl Particle for LambdaParticle {
@@ -247,6 +580,17 @@ impl Particle for LambdaParticle {
     fn is_isomorphic_to(&self, other: &Self) -> bool {
         self.expr.is_isomorphic_to(&other.expr)
     }
+
+    fn size(&self) -> usize {
+        self.expr.size()
+    }
+
+    type Species = CanonicalKey;
+
+    /// Test particles are of the same species as ordinary particles with the same term.
+    fn species(&self) -> CanonicalKey {
+        canonical_key(&self.expr)
+    }
 }
 
 impl Collider<LambdaParticle, LambdaCollisionOk, LambdaCollisionError> for AlchemyCollider {
@@ -263,6 +607,20 @@ impl Collider<LambdaParticle, LambdaCollisionOk, LambdaCollisionError> for Alche
             self.nonrecursive_collide(left, right)
         }
     }
+
+    fn not_enough_expressions(&self) -> LambdaCollisionError {
+        LambdaCollisionError::NotEnoughExpressions
+    }
+
+    /// Catalysts are preserved on the left. Test particles never count as catalysts.
+    fn preserves(&self, position: usize, reactant: &LambdaParticle) -> bool {
+        position == 0 && !reactant.recursive && self.catalyst_set.contains(&reactant.expr)
+    }
+
+    /// With `use_eta`, eta-equivalent particles are one species.
+    fn species(&self, particle: &LambdaParticle) -> CanonicalKey {
+        self.species_key(&particle.expr)
+    }
 }
 
 impl Residue<LambdaParticle> for LambdaCollisionOk {
@@ -275,6 +633,20 @@ impl Residue<LambdaParticle> for LambdaCollisionOk {
     }
 }
 
+#[cfg(feature = "osc")]
+impl LambdaCollisionOk {
+    /// OSC arguments for sonifying this reaction: a frequency in Hz that rises a semitone per
+    /// reduction step (capped at four octaves above 110Hz), and an amplitude in `[0, 1)` that
+    /// grows with the mean product size.
+    pub fn osc_args(&self) -> Vec<f32> {
+        let steps = self.reductions.iter().sum::<usize>().min(48);
+        let freq = 110.0 * 2f32.powf(steps as f32 / 12.0);
+        let mean_size = self.sizes.iter().sum::<usize>() as f32 / self.sizes.len().max(1) as f32;
+        let amp = mean_size / (mean_size + 32.0);
+        vec![freq, amp]
+    }
+}
+
 impl fmt::Display for LambdaCollisionOk {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         Display::fmt("no message", f)
@@ -287,6 +659,9 @@ impl fmt::Display for LambdaCollisionError {
             LambdaCollisionError::IsIdentity => {
                 Display::fmt("collision result is identity function", f)
             }
+            LambdaCollisionError::IsRule => {
+                Display::fmt("collision result is isomorphic to a reaction rule", f)
+            }
             LambdaCollisionError::IsParent => {
                 Display::fmt("collision result is isomorphic to parent", f)
             }
@@ -300,10 +675,16 @@ impl fmt::Display for LambdaCollisionError {
                 Display::fmt("collision result has free variables", f)
             }
 
+            LambdaCollisionError::ExceedsSizeLimit => {
+                Display::fmt("expression exceeds size limit during reduction", f)
+            }
             LambdaCollisionError::ExceedsDepthLimit => {
                 Display::fmt("expression exceeds depth limit during reduction", f)
             }
             LambdaCollisionError::RecursiveArgument => Display::fmt("argument is recursive", f),
+            LambdaCollisionError::Timeout => {
+                Display::fmt("collision exceeds reduction time limit", f)
+            }
             LambdaCollisionError::BadArgument => Display::fmt(
                 "argument is truth-like or doesn't use all of own arguments",
                 f,
@@ -332,22 +713,46 @@ impl LambdaSoup {
         let rng = ChaCha8Rng::from_seed(seed);
         Self {
             expressions: Vec::new(),
+            counts: Default::default(),
+            births: Vec::new(),
             collider: AlchemyCollider::from_config(cfg),
             maintain_constant_population_size: cfg.maintain_constant_population_size,
             discard_parents: cfg.discard_parents,
+            max_species: cfg.max_species,
+            max_population: cfg.max_population,
             rng,
+            #[cfg(feature = "osc")]
+            last_reaction: None,
             n_collisions: 0,
+            n_attempts: 0,
             t: PhantomData,
             e: PhantomData,
         }
     }
 
+    /// Build a soup from `cfg` and fill it with `terms`, as `from_config` followed by
+    /// `add_lambda_expressions`.
+    pub fn from_terms(cfg: &config::Reactor, terms: impl IntoIterator<Item = Term>) -> Self {
+        let mut soup = Self::from_config(cfg);
+        soup.add_lambda_expressions(terms);
+        soup
+    }
+
     pub fn add_lambda_expressions(&mut self, expressions: impl IntoIterator<Item = Term>) {
-        self.expressions
-            .extend(expressions.into_iter().map(|t| LambdaParticle {
-                expr: t,
-                recursive: false,
-            }))
+        self.perturb(expressions.into_iter().map(|t| LambdaParticle {
+            expr: t,
+            recursive: false,
+        }))
+    }
+
+    /// Like `add_lambda_expressions`, but shuffle the new expressions with the soup's RNG so
+    /// that repeating samples (e.g. from `.cycle().take(n)`) don't enter in a fixed pattern.
+    /// Expressions already in the soup keep their order.
+    pub fn add_lambda_expressions_shuffled(&mut self, expressions: impl IntoIterator<Item = Term>) {
+        let start = self.expressions.len();
+        self.add_lambda_expressions(expressions);
+        // All new expressions share the same birth, so `births` needs no shuffling
+        self.expressions[start..].shuffle(&mut self.rng);
     }
 
     pub fn perturb_lambda_expressions<I>(&mut self, nterms: usize, expressions: I)
@@ -356,20 +761,26 @@ impl LambdaSoup {
         <I as IntoIterator>::IntoIter: Clone,
     {
         if self.maintain_constant_population_size {
-            for _ in 0..nterms {
-                let k = self.rng.gen_range(0..self.expressions.len());
-                self.expressions.swap_remove(k);
-            }
+            self.remove_random(nterms);
         }
         self.add_lambda_expressions(expressions.into_iter().cycle().take(nterms))
     }
 
+    /// Add `n` freshly generated expressions from `gen`, modelling an open system with inflow.
+    /// If the population is kept constant, `n` random expressions are removed first, as in
+    /// `perturb_lambda_expressions`.
+    pub fn inject_from_generator(&mut self, gen: &mut (impl Generator + ?Sized), n: usize) {
+        if self.maintain_constant_population_size {
+            self.remove_random(n);
+        }
+        self.add_lambda_expressions(gen.generate_n(n))
+    }
+
     pub fn add_test_expressions(&mut self, expressions: impl IntoIterator<Item = Term>) {
-        self.expressions
-            .extend(expressions.into_iter().map(|t| LambdaParticle {
-                expr: t,
-                recursive: true,
-            }))
+        self.perturb(expressions.into_iter().map(|t| LambdaParticle {
+            expr: t,
+            recursive: true,
+        }))
     }
 
     pub fn perturb_test_expressions<I>(&mut self, nterms: usize, expressions: I)
@@ -378,21 +789,237 @@ impl LambdaSoup {
         <I as IntoIterator>::IntoIter: Clone,
     {
         if self.maintain_constant_population_size {
-            for _ in 0..nterms {
-                let k = self.rng.gen_range(0..self.expressions.len());
-                self.expressions.swap_remove(k);
-            }
+            self.remove_random(nterms);
         }
         self.add_test_expressions(expressions.into_iter().cycle().take(nterms))
     }
 
+    /// Remove every expression whose underlying term fails `keep`.
+    pub fn retain_terms<F: Fn(&Term) -> bool>(&mut self, keep: F) {
+        self.retain(|p| keep(p.get_underlying_term()))
+    }
+
     pub fn lambda_expressions(&self) -> impl Iterator<Item = &Term> {
         self.expressions.iter().map(|e| e.get_underlying_term())
     }
 
+    /// The key of `expr` as a species in this soup: `canonical_key`, or `eta_canonical_key` if
+    /// the reactor's `use_eta` is set.
+    pub fn species_key(&self, expr: &Term) -> CanonicalKey {
+        self.collider.species_key(expr)
+    }
+
+    /// Count the expressions of the same species as `item`, as given by `species_key`.
     pub fn population_of(&self, item: &Term) -> usize {
-        self.lambda_expressions()
-            .filter(|p| p.is_isomorphic_to(item))
-            .count()
+        let key = self.species_key(item);
+        let copies = self
+            .term_counts()
+            .filter(|(t, _)| match self.collider.use_eta {
+                true => eta_canonical_key(t) == key,
+                false => key.matches(t),
+            });
+        copies.map(|(_, c)| c as usize).sum()
+    }
+
+    /// Every distinct expression in the soup with its number of copies. An expression held
+    /// both as a test particle and as an ordinary one appears twice.
+    pub(crate) fn term_counts(&self) -> impl Iterator<Item = (&Term, u32)> {
+        self.particle_counts().iter().map(|(p, c)| (&p.expr, *c))
+    }
+
+    /// Like calling `population_of` for every target, but in a single pass over the soup: each
+    /// expression is matched against all targets with one lookup of its species key.
+    pub fn population_of_any(&self, targets: &[Term]) -> Vec<usize> {
+        self.population_in(&self.term_set(targets))
+    }
+
+    /// Look up and store the reductions of rule compositions in `cache`, which may be shared
+    /// with other soups. Reactions come out the same as without a cache; only repeated
+    /// compositions get cheaper. Test particle reactions are not cached.
+    pub fn use_normal_form_cache(&mut self, cache: NormalFormCache) {
+        self.collider.cache = Some(cache);
+    }
+
+    /// Replace the reaction rules, keeping every other option of the collider, including its
+    /// normal form cache. Per-rule orders are dropped, since they belong to the old rules, so
+    /// every new rule uses the collider's order. See `Soup::set_collider`.
+    pub fn set_rules(&mut self, rules: Vec<String>) {
+        let mut collider = AlchemyCollider::from_config(&config::Reactor {
+            rules,
+            rule_orders: Vec::new(),
+            ..self.collider.to_config()
+        });
+        collider.cache = self.collider.cache.take();
+        self.set_collider(collider);
+    }
+
+    /// A `TermSet` of `targets` that identifies species as this soup does.
+    pub fn term_set(&self, targets: &[Term]) -> TermSet {
+        match self.collider.use_eta {
+            true => TermSet::with_eta(targets),
+            false => TermSet::new(targets),
+        }
+    }
+
+    /// Count the expressions of each target species in `targets`, in target order, in a single
+    /// pass over the soup. Build `targets` with `term_set` to reuse it across polls.
+    pub fn population_in(&self, targets: &TermSet) -> Vec<usize> {
+        targets.count_copies(self.term_counts())
+    }
+
+    /// React until no expression of `target`'s species is left, and return the number of
+    /// reactions that took: `Some(0)` if it is already absent, or `None` if it survives `max`
+    /// reactions or the soup `is_dead` first. The population is recounted after every reaction
+    /// that can change it, so each costs a pass over the soup.
+    pub fn simulate_until_extinct(&mut self, target: &Term, max: usize) -> Option<usize> {
+        if self.population_of(target) == 0 {
+            return Some(0);
+        }
+        for i in 1..=max {
+            if self.is_dead() {
+                break;
+            }
+            let reaction = self.react();
+            let unchanged = reaction.is_err() && !self.discard_parents;
+            if !unchanged && self.population_of(target) == 0 {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    /// React until a product of a species never seen before appears, and return the number of
+    /// reactions that took with the first such product. Species are told apart by
+    /// `species_key`, and "seen" covers every species in the soup at the start of the call
+    /// and every product since, including products that were later consumed or removed.
+    /// Returns `None` if no novel product appears within `max` reactions or before the soup
+    /// `is_dead`.
+    ///
+    /// The seen set holds one key per species and is never pruned, so its memory grows with
+    /// the number of distinct species produced over the call, not with the population.
+    pub fn simulate_until_novel(&mut self, max: usize) -> Option<(usize, Term)> {
+        let mut seen = self
+            .term_counts()
+            .map(|(t, _)| self.species_key(t))
+            .collect::<HashSet<_>>();
+        for i in 1..=max {
+            if self.is_dead() {
+                break;
+            }
+            let Ok(reaction) = self.react() else {
+                continue;
+            };
+            for product in reaction.results {
+                if seen.insert(self.species_key(&product.expr)) {
+                    return Some((i, product.expr));
+                }
+            }
+        }
+        None
+    }
+
+    /// React for `duration` of wall-clock time and return the throughput in reactions
+    /// attempted per second, successful or not. The soup is left in the state the reactions
+    /// took it to, so benchmark a clone to keep the original. Stops early if the soup
+    /// `is_dead`, in which case the rate is over the time actually spent.
+    pub fn benchmark(&mut self, duration: Duration) -> f64 {
+        let start = Instant::now();
+        let mut n = 0;
+        while start.elapsed() < duration && !self.is_dead() {
+            let _ = self.react();
+            n += 1;
+        }
+        n as f64 / start.elapsed().as_secs_f64()
+    }
+
+    /// Simulate the soup for `n` collisions, writing one JSON object per reaction to `writer`,
+    /// one per line, with fields `reaction` (its index), `left` and `right` (the reactants in
+    /// classic notation, with `right` null for a unary collider), `outcome` (`"ok"` or the
+    /// error message), and `reductions` and `sizes` (per product, empty on failure). Returns
+    /// the number of successful reactions.
+    pub fn simulate_for_trace(&mut self, n: usize, writer: &mut impl Write) -> io::Result<usize> {
+        let mut n_successes = 0;
+        for i in 0..n {
+            if self.is_dead() {
+                break;
+            }
+            let mut line = Value::Null;
+            let reaction = self.react_observed(|_, reactants, result| {
+                let (outcome, reductions, sizes) = match result {
+                    Ok(ok) => (String::from("ok"), ok.reductions.clone(), ok.sizes.clone()),
+                    Err(e) => (e.to_string(), Vec::new(), Vec::new()),
+                };
+                line = json!({
+                    "reaction": i,
+                    "left": reactants[0].expr.to_string(),
+                    "right": reactants.get(1).map(|p| p.expr.to_string()),
+                    "outcome": outcome,
+                    "reductions": reductions,
+                    "sizes": sizes,
+                });
+            });
+            if reaction.is_ok() {
+                n_successes += 1;
+            }
+            writeln!(writer, "{line}")?;
+        }
+        Ok(n_successes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{app, combinators::K, parse, term::Notation::Classic, IntoChurchNum};
+
+    use crate::config::{self, ReductionOrder};
+    use crate::supercollider::Collider;
+
+    use super::{reduce_with_limit, AlchemyCollider, LambdaParticle, ReductionOutcome};
+
+    #[test]
+    fn outcomes_tell_loops_from_explosions() {
+        let mut k = app!(K(), 1.into_church(), 2.into_church());
+        assert_eq!(
+            reduce_with_limit(&mut k, 100, 100),
+            ReductionOutcome::Normalized(2)
+        );
+
+        let mut omega = parse(r"(\x.x x) (\x.x x)", Classic).unwrap();
+        let size = omega.size();
+        assert_eq!(
+            reduce_with_limit(&mut omega, 100, 100),
+            ReductionOutcome::HitStepLimit(size)
+        );
+
+        let mut explosion = parse(r"(\x.x x x) (\x.x x x)", Classic).unwrap();
+        let outcome = reduce_with_limit(&mut explosion, 100, 100);
+        assert!(matches!(outcome, ReductionOutcome::HitSizeLimit(n) if n < 100));
+        assert!(outcome.into_result().is_err());
+    }
+
+    #[test]
+    fn rules_can_override_the_reduction_order() {
+        assert_eq!(
+            serde_json::to_string(&ReductionOrder::Hap).unwrap(),
+            r#""HAP""#
+        );
+        assert_eq!(
+            serde_json::from_str::<ReductionOrder>(r#""NOR""#).unwrap(),
+            ReductionOrder::Nor
+        );
+
+        // Drops `x x`, which diverges for x = \x.x x unless it is left unreduced
+        let rule = r"\x.\y.(\a.\b.b) (x x) y";
+        let cfg = config::Reactor {
+            rule_orders: vec![Some(ReductionOrder::Nor)],
+            ..config::Reactor::default_with_rule(rule, config::ConfigSeed::new([0; 32]))
+        };
+        let collider = AlchemyCollider::from_config(&cfg);
+        assert_eq!(collider.to_config().reduction_order, ReductionOrder::Hap);
+        assert_eq!(collider.to_config().rule_orders, cfg.rule_orders);
+
+        let omega = parse(r"\x.x x", Classic).unwrap();
+        let residue = collider.collide(LambdaParticle::new(omega), LambdaParticle::new(K()));
+        assert_eq!(residue.unwrap().results[0].expr, K());
     }
 }
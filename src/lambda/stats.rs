@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+
+use lambda_calculus::Term;
+
+/// Structural statistics of a lambda expression, computed in a single traversal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermStats {
+    /// Number of nodes (variables, abstractions and applications)
+    pub size: usize,
+
+    /// Number of abstractions and applications on the longest path from the root to a
+    /// variable, so a lone variable has depth `0`
+    pub depth: usize,
+
+    /// Number of abstractions the expression starts with, i.e. how many arguments it takes
+    pub n_leading_binders: usize,
+
+    /// Number of distinct free variables
+    pub n_free_vars: usize,
+
+    /// Whether every leading binder is referenced in the body
+    pub uses_all_args: bool,
+}
+
+impl TermStats {
+    pub fn of(expr: &Term) -> TermStats {
+        let mut stats = TermStats {
+            size: 0,
+            depth: 0,
+            n_leading_binders: 0,
+            n_free_vars: 0,
+            uses_all_args: true,
+        };
+
+        let mut body = expr;
+        while let Term::Abs(ref inner) = body {
+            stats.n_leading_binders += 1;
+            body = inner;
+        }
+        let n = stats.n_leading_binders;
+
+        let mut used = vec![false; n];
+        let mut free = HashSet::new();
+        let body_depth = visit(body, n, &mut stats.size, &mut used, &mut free);
+
+        stats.size += n;
+        stats.depth = n + body_depth;
+        stats.n_free_vars = free.len();
+        stats.uses_all_args = used.into_iter().all(|u| u);
+        stats
+    }
+}
+
+/// Nesting depth of `expr`, as in `TermStats::depth`, without computing the other statistics.
+/// This is the depth reduction recurses to, which can be small for a large term.
+pub fn term_depth(expr: &Term) -> usize {
+    match expr {
+        Term::Var(_) => 0,
+        Term::Abs(ref body) => 1 + term_depth(body),
+        Term::App(ref boxed) => {
+            let (ref left, ref right) = **boxed;
+            1 + term_depth(left).max(term_depth(right))
+        }
+    }
+}
+
+/// Number of arguments `expr` takes: the abstractions it starts with, as in
+/// `TermStats::n_leading_binders`. Abstractions anywhere else do not count, so `\x. x (\y. y)`
+/// has arity 1 and a variable or an application has arity 0, whatever it reduces to.
+pub fn arity(expr: &Term) -> usize {
+    let mut n = 0;
+    let mut body = expr;
+    while let Term::Abs(ref inner) = body {
+        n += 1;
+        body = inner;
+    }
+    n
+}
+
+/// Walk `expr` under `binders` enclosing abstractions, adding its nodes to `size`, marking the
+/// leading binders it references in `used` and collecting the free variables (as indices
+/// relative to the root) in `free`. Returns the depth of `expr`.
+fn visit(
+    expr: &Term,
+    binders: usize,
+    size: &mut usize,
+    used: &mut [bool],
+    free: &mut HashSet<usize>,
+) -> usize {
+    *size += 1;
+    match expr {
+        Term::Var(i) => {
+            if *i > binders {
+                free.insert(i - binders);
+            } else {
+                // Binder `binders - i` counting from the root; only the leading ones are tracked
+                if let Some(u) = used.get_mut(binders - i) {
+                    *u = true;
+                }
+            }
+            0
+        }
+        Term::Abs(ref body) => 1 + visit(body, binders + 1, size, used, free),
+        Term::App(ref boxed) => {
+            let (ref left, ref right) = **boxed;
+            let l = visit(left, binders, size, used, free);
+            let r = visit(right, binders, size, used, free);
+            1 + l.max(r)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        abs, app,
+        combinators::{I, K, S},
+        data::num::church::succ,
+        Term::Var,
+    };
+
+    use super::{arity, term_depth, TermStats};
+
+    #[test]
+    fn combinator_stats() {
+        assert_eq!(
+            TermStats::of(&I()),
+            TermStats {
+                size: 2,
+                depth: 1,
+                n_leading_binders: 1,
+                n_free_vars: 0,
+                uses_all_args: true,
+            }
+        );
+        assert_eq!(
+            TermStats::of(&K()),
+            TermStats {
+                size: 3,
+                depth: 2,
+                n_leading_binders: 2,
+                n_free_vars: 0,
+                uses_all_args: false,
+            }
+        );
+        assert_eq!(
+            TermStats::of(&S()),
+            TermStats {
+                size: 10,
+                depth: 5,
+                n_leading_binders: 3,
+                n_free_vars: 0,
+                uses_all_args: true,
+            }
+        );
+        assert_eq!(term_depth(&S()), 5);
+    }
+
+    #[test]
+    fn free_variables_are_counted_once() {
+        // \x. x y y z
+        let t = abs(app!(Var(1), Var(2), Var(2), Var(3)));
+        let stats = TermStats::of(&t);
+        assert_eq!(stats.n_free_vars, 2);
+        assert!(stats.uses_all_args);
+    }
+
+    #[test]
+    fn arity_counts_leading_binders() {
+        assert_eq!(arity(&I()), 1);
+        assert_eq!(arity(&K()), 2);
+        assert_eq!(arity(&S()), 3);
+        assert_eq!(arity(&succ()), 3);
+        // \x. x (\y. y)
+        assert_eq!(arity(&abs(app!(Var(1), I()))), 1);
+        assert_eq!(arity(&app!(I(), K())), 0);
+    }
+}
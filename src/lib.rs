@@ -3,6 +3,7 @@ use pyo3::prelude::*;
 
 // Re-export your Rust modules for the CLI and for external users
 pub mod analysis;
+pub mod collidable;
 pub mod config;
 pub mod experiments;
 pub mod generators;
@@ -3,7 +3,6 @@ use clap::{Parser, ValueEnum};
 use experiments::{
     discovery, distribution, entropy, kinetics, magic_test_function, search_by_behavior,
 };
-use generators::BTreeGen;
 use std::fs::{read_to_string, File};
 use std::io::Write;
 
@@ -118,23 +117,48 @@ fn get_config(cli: &Cli) -> std::io::Result<config::Config> {
 
 // main.rs
 pub fn generate_expressions_and_seed_soup(cfg: &config::Config) -> lambda::recursive::LambdaSoup {
-    let expressions = match &cfg.generator_config {
-        config::Generator::BTree(gen_cfg) => {
-            let mut gen = generators::BTreeGen::from_config(gen_cfg);
-            gen.generate_n(cfg.sample_size)
-        }
-        config::Generator::Fontana(gen_cfg) => {
-            let mut gen = generators::FontanaGen::from_config(gen_cfg);
-            gen.generate_n(cfg.sample_size) // ← returns Vec<Term>
-        }
-    };
+    let expressions = generators::from_config(&cfg.generator_config).generate_n(cfg.sample_size);
     let mut soup = lambda::recursive::LambdaSoup::from_config(&cfg.reactor_config);
     soup.add_lambda_expressions(expressions);
     soup
 }
 
+fn print_entropy_and_failures(rows: &[(usize, f32, f32)]) {
+    println!("Soup, Entropy, Failure rate");
+    for (id, entropy, failure_rate) in rows {
+        println!("{}, {}, {}", id, entropy, failure_rate);
+    }
+}
+
+fn print_sync_entropy_and_failures(rows: &[(usize, f32)]) {
+    for (id, entropy) in rows {
+        println!("{}: {}", id, entropy);
+    }
+}
+
+fn print_entropy_time_series(rows: &[(usize, Vec<f32>)]) {
+    let polls = rows.iter().map(|(_, data)| data.len()).max().unwrap_or(0);
+    print!("Soup, ");
+    for i in 0..polls {
+        print!("{}, ", i)
+    }
+    println!();
+    for (id, data) in rows {
+        print!("{}, ", id);
+        for i in data {
+            print!("{}, ", i)
+        }
+        println!();
+    }
+}
+
 fn main() -> std::io::Result<()> {
     let cli = Cli::parse();
+    // Diagnostics go to stderr, keeping stdout for data. `--log` needs `info` to be shown;
+    // `RUST_LOG` overrides the default either way.
+    let default_level = if cli.log { "info" } else { "warn" };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_level))
+        .init();
 
     if cli.make_default_config {
         let config_path = "config.json";
@@ -151,28 +175,24 @@ fn main() -> std::io::Result<()> {
     }
 
     if let Some(n) = cli.generate {
-        match &config.generator_config {
-            config::Generator::BTree(gen_cfg) => {
-                let mut gen = BTreeGen::from_config(gen_cfg);
-                for _ in 0..n {
-                    println!("{:?}", gen.generate())
-                }
-            }
-            config::Generator::Fontana(gen_cfg) => {
-                let mut gen = generators::FontanaGen::from_config(gen_cfg);
-                for _ in 0..n {
-                    println!("{:?}", gen.generate())
-                }
-            }
+        let mut gen = generators::from_config(&config.generator_config);
+        for _ in 0..n {
+            println!("{:?}", gen.generate())
         }
         return Ok(());
     }
 
     if let Some(e) = cli.experiment {
         match e {
-            Experiment::EntropyAndFailures => entropy::entropy_and_failures(),
-            Experiment::SyncEntropyAndFailures => entropy::sync_entropy_and_failures(),
-            Experiment::EntropyTimeSeries => entropy::entropy_time_series(),
+            Experiment::EntropyAndFailures => {
+                print_entropy_and_failures(&entropy::entropy_and_failures())
+            }
+            Experiment::SyncEntropyAndFailures => {
+                print_sync_entropy_and_failures(&entropy::sync_entropy_and_failures())
+            }
+            Experiment::EntropyTimeSeries => {
+                print_entropy_time_series(&entropy::entropy_time_series())
+            }
 
             Experiment::XorsetSearch => search_by_behavior::look_for_xorset(),
             Experiment::NotXorsetSearch => search_by_behavior::look_for_not_xorset(),
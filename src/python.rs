@@ -1,24 +1,33 @@
 // src/python.rs
+use pyo3::exceptions::PyKeyboardInterrupt;
 use pyo3::prelude::*;
 use pyo3::wrap_pyfunction;
 use serde::{Deserialize, Serialize};
 use rand::Rng; // Import Rng for random seed generation
 
-use lambda_calculus::{parse, term::Notation::Classic};
+use lambda_calculus::{
+    parse,
+    term::Notation::{self, Classic, DeBruijn},
+    Term,
+};
 
 use crate::config::{self, ConfigSeed, Reactor as RustReactor};
 use crate::generators::{
-    BTreeGen as RustBTreeGen, FontanaGen as RustFontanaGen, Standardization as RustStandardization,
+    BTreeGen as RustBTreeGen, FontanaGen as RustFontanaGen, Generator,
+    Standardization as RustStandardization,
 };
+use crate::lambda;
 use crate::lambda::recursive::{
     AlchemyCollider, LambdaCollisionError, LambdaCollisionOk, LambdaParticle,
 };
-use crate::supercollider::Soup as GenericSoup;
-use crate::utils::{decode_hex, encode_hex};
+use crate::supercollider::{Soup as GenericSoup, Tape as GenericTape};
+use crate::utils::{decode_hex, encode_hex, parse_inputs};
 
 // Concrete soup alias for the recursive lambda flavor
 type RustSoup =
     GenericSoup<LambdaParticle, AlchemyCollider, LambdaCollisionOk, LambdaCollisionError>;
+type RustTape =
+    GenericTape<LambdaParticle, AlchemyCollider, LambdaCollisionOk, LambdaCollisionError>;
 
 // ============ Helper for Seed Parsing ============
 
@@ -40,6 +49,27 @@ fn parse_seed(seed_hex: Option<String>) -> PyResult<[u8; 32]> {
     }
 }
 
+// ============ Helpers for Term Notation ============
+
+fn parse_notation(notation: &str) -> PyResult<Notation> {
+    match notation {
+        "classic" => Ok(Classic),
+        "debruijn" => Ok(DeBruijn),
+        _ => Err(pyo3::exceptions::PyValueError::new_err(
+            "Invalid notation, expected \"classic\" or \"debruijn\"",
+        )),
+    }
+}
+
+// `Display` prints classic notation and `Debug` prints De Bruijn notation; both parse back
+// with `lambda_calculus::parse` given the same notation.
+fn format_term(t: &Term, notation: Notation) -> String {
+    match notation {
+        Classic => t.to_string(),
+        DeBruijn => format!("{:?}", t),
+    }
+}
+
 // ============ Errors exposed to Python ============
 
 #[pyclass]
@@ -47,6 +77,7 @@ fn parse_seed(seed_hex: Option<String>) -> PyResult<[u8; 32]> {
 #[allow(dead_code)]
 pub struct PyReactionError {
     kind: ReactionErrorKind,
+    message: String,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -54,11 +85,14 @@ pub enum ReactionErrorKind {
     ExceedsReductionLimit,
     NotEnoughExpressions,
     IsIdentity,
+    IsRule,
     IsParent,
     HasFreeVariables,
+    ExceedsSizeLimit,
     ExceedsDepthLimit,
     RecursiveArgument,
     BadArgument,
+    Timeout,
 }
 
 impl ReactionErrorKind {
@@ -67,11 +101,14 @@ impl ReactionErrorKind {
             ReactionErrorKind::ExceedsReductionLimit => "exceeds_reduction_limit",
             ReactionErrorKind::NotEnoughExpressions => "not_enough_expressions",
             ReactionErrorKind::IsIdentity => "is_identity",
+            ReactionErrorKind::IsRule => "is_rule",
             ReactionErrorKind::IsParent => "is_parent",
             ReactionErrorKind::HasFreeVariables => "has_free_variables",
+            ReactionErrorKind::ExceedsSizeLimit => "exceeds_size_limit",
             ReactionErrorKind::ExceedsDepthLimit => "exceeds_depth_limit",
             ReactionErrorKind::RecursiveArgument => "recursive_argument",
             ReactionErrorKind::BadArgument => "bad_argument",
+            ReactionErrorKind::Timeout => "timeout",
         }
     }
 }
@@ -82,21 +119,36 @@ impl PyReactionError {
     fn kind(&self) -> &'static str {
         self.kind.as_str()
     }
+
+    fn __str__(&self) -> String {
+        self.message.clone()
+    }
+}
+
+/// What `PySoup.react_pair` returns: the products, or why the reaction failed.
+#[derive(IntoPyObject)]
+enum ReactionOutcome {
+    Products(Vec<String>),
+    Failed(PyReactionError),
 }
 
 impl From<LambdaCollisionError> for PyReactionError {
     fn from(error: LambdaCollisionError) -> Self {
+        let message = error.to_string();
         let kind = match error {
             LambdaCollisionError::ExceedsReductionLimit => ReactionErrorKind::ExceedsReductionLimit,
             LambdaCollisionError::NotEnoughExpressions => ReactionErrorKind::NotEnoughExpressions,
             LambdaCollisionError::IsIdentity => ReactionErrorKind::IsIdentity,
+            LambdaCollisionError::IsRule => ReactionErrorKind::IsRule,
             LambdaCollisionError::IsParent => ReactionErrorKind::IsParent,
             LambdaCollisionError::HasFreeVariables => ReactionErrorKind::HasFreeVariables,
+            LambdaCollisionError::ExceedsSizeLimit => ReactionErrorKind::ExceedsSizeLimit,
             LambdaCollisionError::ExceedsDepthLimit => ReactionErrorKind::ExceedsDepthLimit,
             LambdaCollisionError::RecursiveArgument => ReactionErrorKind::RecursiveArgument,
             LambdaCollisionError::BadArgument => ReactionErrorKind::BadArgument,
+            LambdaCollisionError::Timeout => ReactionErrorKind::Timeout,
         };
-        PyReactionError { kind }
+        PyReactionError { kind, message }
     }
 }
 
@@ -115,6 +167,73 @@ impl PyReactor {
             inner: RustReactor::new(),
         }
     }
+
+    /// A named chemistry from `preset_names()`, seeded with `seed` (random if omitted).
+    #[staticmethod]
+    #[pyo3(signature = (name, seed=None))]
+    fn preset(name: &str, seed: Option<String>) -> PyResult<Self> {
+        let seed = ConfigSeed::new(parse_seed(seed)?);
+        let inner = config::presets::by_name(name, seed).ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err(format!("Unknown preset: {}", name))
+        })?;
+        Ok(PyReactor { inner })
+    }
+
+    /// Wall-clock limit, in milliseconds, for reducing a single collision. `None` disables it.
+    #[getter]
+    fn reduction_timeout_ms(&self) -> Option<u64> {
+        self.inner.reduction_timeout_ms
+    }
+
+    #[setter]
+    fn set_reduction_timeout_ms(&mut self, timeout: Option<u64>) {
+        self.inner.reduction_timeout_ms = timeout;
+    }
+
+    /// Largest nesting depth of an expression during reduction. `None` disables it.
+    #[getter]
+    fn depth_cutoff(&self) -> Option<usize> {
+        self.inner.depth_cutoff
+    }
+
+    #[setter]
+    fn set_depth_cutoff(&mut self, cutoff: Option<usize>) {
+        self.inner.depth_cutoff = cutoff;
+    }
+
+    /// Extra `(reductions, size)` for one retry of a reduction that exceeds the reduction
+    /// cutoff. `None` disables the retry.
+    #[getter]
+    fn escalation(&self) -> Option<(usize, usize)> {
+        self.inner.escalation
+    }
+
+    #[setter]
+    fn set_escalation(&mut self, escalation: Option<(usize, usize)>) {
+        self.inner.escalation = escalation;
+    }
+
+    /// Most reduction steps for a single collision.
+    #[getter]
+    fn reduction_cutoff(&self) -> usize {
+        self.inner.reduction_cutoff
+    }
+
+    #[setter]
+    fn set_reduction_cutoff(&mut self, cutoff: usize) {
+        self.inner.reduction_cutoff = cutoff;
+    }
+
+    /// Largest size of an expression during reduction.
+    #[getter]
+    fn size_cutoff(&self) -> usize {
+        self.inner.size_cutoff
+    }
+
+    #[setter]
+    fn set_size_cutoff(&mut self, cutoff: usize) {
+        self.inner.size_cutoff = cutoff;
+    }
 }
 
 // ============ Standardization wrapper ============
@@ -125,21 +244,52 @@ pub struct PyStandardization {
     standardization: RustStandardization,
 }
 
+/// The standardization kinds, by the names Python uses for them.
+const STANDARDIZATIONS: [(&str, RustStandardization); 3] = [
+    ("prefix", RustStandardization::Prefix),
+    ("postfix", RustStandardization::Postfix),
+    ("none", RustStandardization::None),
+];
+
 #[pymethods]
 impl PyStandardization {
     #[new]
     fn new(kind: &str) -> PyResult<Self> {
-        let standardization = match kind {
-            "prefix" => RustStandardization::Prefix,
-            "postfix" => RustStandardization::Postfix,
-            "none" => RustStandardization::None,
-            _ => {
-                return Err(pyo3::exceptions::PyValueError::new_err(
-                    "Invalid standardization type",
-                ))
-            }
-        };
-        Ok(PyStandardization { standardization })
+        match STANDARDIZATIONS.iter().find(|(name, _)| *name == kind) {
+            Some(&(_, standardization)) => Ok(PyStandardization { standardization }),
+            None => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Invalid standardization type {:?}, expected one of {:?}",
+                kind,
+                Self::values()
+            ))),
+        }
+    }
+
+    /// The kinds accepted by the constructor.
+    #[staticmethod]
+    fn values() -> Vec<&'static str> {
+        STANDARDIZATIONS.iter().map(|(name, _)| *name).collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Standardization('{}')", self.kind())
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.standardization == other.standardization
+    }
+
+    fn __hash__(&self) -> u64 {
+        self.standardization as u64
+    }
+}
+
+impl PyStandardization {
+    fn kind(&self) -> &'static str {
+        let kind = STANDARDIZATIONS
+            .iter()
+            .find(|(_, s)| *s == self.standardization);
+        kind.map(|(name, _)| *name).unwrap()
     }
 }
 
@@ -172,6 +322,22 @@ impl PySoup {
         }
     }
 
+    /// Build a soup from `cfg` filled with `expressions`. Raises `ValueError` if any
+    /// expression fails to parse.
+    #[staticmethod]
+    fn from_terms(cfg: &PyReactor, expressions: Vec<String>) -> PyResult<Self> {
+        let (terms, errors) = parse_inputs(&expressions);
+        if let Some((i, e)) = errors.first() {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Cannot parse expression {i} ({:?}): {e}",
+                expressions[*i]
+            )));
+        }
+        Ok(PySoup {
+            inner: RustSoup::from_terms(&cfg.inner, terms),
+        })
+    }
+
     fn perturb(&mut self, expressions: Vec<String>) {
         let terms = expressions
             .into_iter()
@@ -179,12 +345,100 @@ impl PySoup {
         self.inner.add_lambda_expressions(terms);
     }
 
-    fn simulate_for(&mut self, n: usize, log: bool) -> usize {
-        self.inner.simulate_for(n, log)
+    /// Like `perturb`, but return `(index, error)` for every expression that failed to parse
+    /// instead of dropping it silently. The expressions that did parse are still added.
+    fn perturb_checked(&mut self, expressions: Vec<String>) -> Vec<(usize, String)> {
+        let (terms, errors) = parse_inputs(&expressions);
+        self.inner.add_lambda_expressions(terms);
+        errors
+            .into_iter()
+            .map(|(i, e)| (i, e.to_string()))
+            .collect()
+    }
+
+    /// Collide `left` with `right` directly, adding the products to the soup on success as
+    /// `Soup::react_pair` does. Returns the products, or a `PyReactionError` saying why the
+    /// reaction failed. Raises `ValueError` if either expression fails to parse.
+    fn react_pair(&mut self, left: &str, right: &str) -> PyResult<ReactionOutcome> {
+        let parse_particle = |expr: &str| {
+            parse(expr, Classic)
+                .map(LambdaParticle::new)
+                .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+        };
+        let (left, right) = (parse_particle(left)?, parse_particle(right)?);
+        Ok(match self.inner.react_pair(left, right) {
+            Ok(residue) => ReactionOutcome::Products(
+                residue.results.iter().map(|p| p.expr.to_string()).collect(),
+            ),
+            Err(e) => ReactionOutcome::Failed(e.into()),
+        })
+    }
+
+    /// Run `n` reactions and return the number that succeeded. Every `progress_interval`
+    /// reactions, pending signals are checked and `progress(done, n_successes)` is called if
+    /// given. The run stops early, keeping the soup as it is, if `progress` returns `False` or
+    /// a `KeyboardInterrupt` (e.g. Ctrl-C) arrives.
+    #[pyo3(signature = (n, log, progress=None, progress_interval=1000))]
+    fn simulate_for(
+        &mut self,
+        py: Python<'_>,
+        n: usize,
+        log: bool,
+        progress: Option<PyObject>,
+        progress_interval: usize,
+    ) -> PyResult<usize> {
+        if progress_interval == 0 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "progress_interval must be positive",
+            ));
+        }
+        let is_interrupt = |e: &PyErr| e.is_instance_of::<PyKeyboardInterrupt>(py);
+
+        let mut done = 0;
+        let mut n_successes = 0;
+        while done < n {
+            let chunk = progress_interval.min(n - done);
+            n_successes += self.inner.simulate_for(chunk, log);
+            done += chunk;
+
+            match py.check_signals() {
+                Err(e) if is_interrupt(&e) => break,
+                result => result?,
+            }
+            if let Some(ref callback) = progress {
+                match callback.call1(py, (done, n_successes)) {
+                    Ok(keep_going) => {
+                        if let Ok(false) = keep_going.extract::<bool>(py) {
+                            break;
+                        }
+                    }
+                    Err(e) if is_interrupt(&e) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(n_successes)
     }
+
+    /// Run `n` reactions, keeping a copy of the soup every `interval` reactions. Each copy
+    /// holds the whole population, so keep `n / interval` small for large soups.
+    fn simulate_and_record(&mut self, n: usize, interval: usize) -> PyResult<PyTape> {
+        if interval == 0 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "interval must be positive",
+            ));
+        }
+        Ok(PyTape {
+            inner: self.inner.simulate_and_record(n, interval, false),
+        })
+    }
+
     fn len(&self) -> usize {
         self.inner.len()
     }
+    fn is_dead(&self) -> bool {
+        self.inner.is_dead()
+    }
     fn collisions(&self) -> usize {
         self.inner.collisions()
     }
@@ -197,21 +451,68 @@ impl PySoup {
     }
     fn unique_expressions(&self) -> Vec<String> {
         self.inner
-            .unique_expressions()
+            .unique_expressions_sorted()
             .into_iter()
             .map(|t| t.to_string())
             .collect()
     }
-    fn expression_counts(&self) -> Vec<(String, u32)> {
+    /// Count the copies of each species, merging alpha-variants. Species are rendered with
+    /// `pretty` names if `pretty` is set, and in the default classic notation otherwise.
+    #[pyo3(signature = (pretty=true))]
+    fn expression_counts(&self, pretty: bool) -> Vec<(String, u32)> {
         self.inner
             .expression_counts()
             .into_iter()
-            .map(|(t, c)| (t.to_string(), c))
+            .map(|(t, c)| match pretty {
+                true => (lambda::pretty(&t), c),
+                false => (t.to_string(), c),
+            })
             .collect()
     }
     fn population_entropy(&self) -> f32 {
         self.inner.population_entropy()
     }
+    #[pyo3(signature = (base=2.0))]
+    fn population_entropy_base(&self, base: f32) -> f32 {
+        self.inner.population_entropy_base(base)
+    }
+    fn normalized_entropy(&self) -> f32 {
+        self.inner.normalized_entropy()
+    }
+    fn simpson_diversity(&self) -> f32 {
+        self.inner.simpson_diversity()
+    }
+}
+
+// ============ Tape wrapper ============
+
+#[pyclass]
+pub struct PyTape {
+    inner: RustTape,
+}
+
+#[pymethods]
+impl PyTape {
+    /// The soup at the end of the recorded run.
+    fn final_state(&self) -> PySoup {
+        PySoup {
+            inner: self.inner.final_state().clone(),
+        }
+    }
+
+    fn polling_interval(&self) -> usize {
+        self.inner.polling_interval()
+    }
+
+    /// Call `callback` on every recorded snapshot, as a `PySoup`, and return `(index, result)`
+    /// pairs. Multiply the index by `polling_interval()` to get the reaction it was taken at.
+    fn to_series(&self, py: Python<'_>, callback: PyObject) -> PyResult<Vec<(usize, PyObject)>> {
+        self.inner
+            .to_series(|s| callback.call1(py, (PySoup { inner: s.clone() },)))
+            .into_iter()
+            .map(|(i, r)| r.map(|r| (i, r)))
+            .collect()
+    }
 }
 
 // ============ Generators ============
@@ -219,49 +520,59 @@ impl PySoup {
 #[pyclass]
 pub struct PyBTreeGen {
     inner: RustBTreeGen,
+    notation: Notation,
 }
 
 #[pymethods]
 impl PyBTreeGen {
     #[new]
-    fn new() -> Self {
-        PyBTreeGen {
+    #[pyo3(signature = (notation="classic"))]
+    fn new(notation: &str) -> PyResult<Self> {
+        Ok(PyBTreeGen {
             inner: RustBTreeGen::new(),
-        }
+            notation: parse_notation(notation)?,
+        })
     }
 
     #[staticmethod]
-    #[pyo3(signature = (size, freevar_generation_probability, max_free_vars, std, seed=None))]
+    #[pyo3(signature = (size, freevar_generation_probability, max_free_vars, std, seed=None, notation="classic", max_depth=None))]
     fn from_config(
         size: u32,
         freevar_generation_probability: f64,
         max_free_vars: u32,
         std: PyStandardization,
         seed: Option<String>,
+        notation: &str,
+        max_depth: Option<usize>,
     ) -> PyResult<Self> {
         let seed_bytes = parse_seed(seed)?;
-        
+        let size = config::SizeSpec::Fixed(size);
+        size.validate()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+
         let cfg = config::BTreeGen {
             size,
             freevar_generation_probability,
             n_max_free_vars: max_free_vars,
             standardization: std.into(),
+            max_depth,
             seed: ConfigSeed::new(seed_bytes),
         };
         Ok(PyBTreeGen {
             inner: RustBTreeGen::from_config(&cfg),
+            notation: parse_notation(notation)?,
         })
     }
 
     fn generate(&mut self) -> String {
-        self.inner.generate().to_string()
+        format_term(&self.inner.generate(), self.notation)
     }
 
     fn generate_n(&mut self, n: usize) -> Vec<String> {
         self.inner
             .generate_n(n)
-            .into_iter()
-            .map(|t| t.to_string())
+            .iter()
+            .map(|t| format_term(t, self.notation))
             .collect()
     }
 }
@@ -269,13 +580,15 @@ impl PyBTreeGen {
 #[pyclass]
 pub struct PyFontanaGen {
     inner: RustFontanaGen,
+    notation: Notation,
 }
 
 #[pymethods]
 impl PyFontanaGen {
     /// Build a Fontana generator from config values
     #[staticmethod]
-    #[pyo3(signature = (abs_range, app_range, min_depth, max_depth, free_variable_probability, max_free_vars, seed=None))]
+    #[pyo3(signature = (abs_range, app_range, min_depth, max_depth, free_variable_probability, max_free_vars, seed=None, notation="classic", flatten_sizes=false, target_per_size=100, close_terms=true))]
+    #[allow(clippy::too_many_arguments)]
     pub fn from_config(
         abs_range: (f64, f64),
         app_range: (f64, f64),
@@ -284,6 +597,10 @@ impl PyFontanaGen {
         free_variable_probability: f64,
         max_free_vars: u32,
         seed: Option<String>,
+        notation: &str,
+        flatten_sizes: bool,
+        target_per_size: usize,
+        close_terms: bool,
     ) -> PyResult<Self> {
         let seed_bytes = parse_seed(seed)?;
 
@@ -295,23 +612,27 @@ impl PyFontanaGen {
             free_variable_probability,
             n_max_free_vars: max_free_vars,
             seed: ConfigSeed::new(seed_bytes),
+            close_terms,
+            flatten_sizes,
+            target_per_size,
         };
         Ok(PyFontanaGen {
             inner: RustFontanaGen::from_config(&cfg),
+            notation: parse_notation(notation)?,
         })
     }
 
     /// Generate a single lambda term
     pub fn generate(&mut self) -> String {
-        self.inner.generate().to_string()
+        format_term(&self.inner.generate(), self.notation)
     }
 
     /// Convenience: generate N terms
     pub fn generate_n(&mut self, n: usize) -> Vec<String> {
         self.inner
             .generate_n(n)
-            .into_iter()
-            .map(|t| t.to_string())
+            .iter()
+            .map(|t| format_term(t, self.notation))
             .collect()
     }
 }
@@ -329,10 +650,44 @@ fn encode_hex_py(bytes: Vec<u8>) -> String {
     encode_hex(&bytes)
 }
 
+/// Wrap a classic-notation expression in abstractions until it has no free variables.
+#[pyfunction]
+fn close_term(expr: &str) -> PyResult<String> {
+    let term =
+        parse(expr, Classic).map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+    Ok(lambda::close_term(term).to_string())
+}
+
+/// Render an expression in `notation` ("classic" or "debruijn") with readable bound-variable
+/// names (x, y, z, ...).
+#[pyfunction]
+#[pyo3(signature = (expr, notation="classic"))]
+fn pretty(expr: &str, notation: &str) -> PyResult<String> {
+    let term = parse(expr, parse_notation(notation)?)
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+    Ok(lambda::pretty(&term))
+}
+
+/// Number of leading abstractions of a classic-notation expression, i.e. how many arguments
+/// it takes.
+#[pyfunction]
+fn arity(expr: &str) -> PyResult<usize> {
+    let term =
+        parse(expr, Classic).map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+    Ok(lambda::arity(&term))
+}
+
+/// The names accepted by `PyReactor.preset`.
+#[pyfunction]
+fn preset_names() -> Vec<&'static str> {
+    config::presets::NAMES.to_vec()
+}
+
 // ============ Public registration hook ============
 
 pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PySoup>()?;
+    m.add_class::<PyTape>()?;
     m.add_class::<PyReactor>()?;
     m.add_class::<PyReactionError>()?;
     m.add_class::<PyStandardization>()?;
@@ -340,5 +695,9 @@ pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyFontanaGen>()?;
     m.add_function(wrap_pyfunction!(decode_hex_py, m)?)?;
     m.add_function(wrap_pyfunction!(encode_hex_py, m)?)?;
+    m.add_function(wrap_pyfunction!(close_term, m)?)?;
+    m.add_function(wrap_pyfunction!(pretty, m)?)?;
+    m.add_function(wrap_pyfunction!(arity, m)?)?;
+    m.add_function(wrap_pyfunction!(preset_names, m)?)?;
     Ok(())
 }
\ No newline at end of file
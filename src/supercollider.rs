@@ -1,15 +1,34 @@
 use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fmt::{Debug, Display},
+    hash::Hash,
     marker::PhantomData,
 };
 
-use rand::Rng;
+#[cfg(feature = "osc")]
+use std::{
+    io,
+    net::{ToSocketAddrs, UdpSocket},
+};
+
+use log::info;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
 
 pub trait Particle {
     fn compose(&self, other: &Self) -> Self;
 
     fn is_isomorphic_to(&self, other: &Self) -> bool;
+
+    /// A measure of the particle's size, used by the soup to prune oversized particles.
+    fn size(&self) -> usize;
+
+    /// The identity of a particle as a species, under which `Soup` counts it.
+    type Species: Debug + Clone + Eq + Hash;
+
+    /// This particle's species, unless its collider identifies more particles (see
+    /// `Collider::species`).
+    fn species(&self) -> Self::Species;
 }
 
 pub trait Collider<P, T, E>
@@ -17,6 +36,40 @@ where
     P: Particle,
 {
     fn collide(&self, left: P, right: P) -> Result<T, E>;
+
+    /// The error `Soup::react` returns, without drawing or colliding anything, when the soup
+    /// holds fewer than `arity()` particles.
+    fn not_enough_expressions(&self) -> E;
+
+    /// Number of reactants `Soup::react` draws for each reaction.
+    fn arity(&self) -> usize {
+        2
+    }
+
+    /// Collide the `arity()` reactants drawn by `Soup::react`, in the order they were drawn.
+    /// Colliders with an arity other than 2 must override this; the default passes the two
+    /// reactants to `collide`.
+    fn collide_all(&self, reactants: Vec<P>) -> Result<T, E> {
+        let mut reactants = reactants.into_iter();
+        let (Some(left), Some(right)) = (reactants.next(), reactants.next()) else {
+            panic!("binary collider given fewer than two reactants");
+        };
+        self.collide(left, right)
+    }
+
+    /// Whether the reactant drawn at `position` survives its reaction, as a catalyst would,
+    /// whatever the outcome. When any reactant is preserved, `Soup::react` consumes the others
+    /// on success even if the soup keeps parents. `Soup::react` asks before colliding. The
+    /// default preserves nothing.
+    fn preserves(&self, _position: usize, _reactant: &P) -> bool {
+        false
+    }
+
+    /// The species `Soup` counts `particle` as. The default is `Particle::species`; colliders
+    /// that treat more particles as one species, such as eta-equivalent ones, override it.
+    fn species(&self, particle: &P) -> P::Species {
+        particle.species()
+    }
 }
 
 pub trait Residue<P>
@@ -30,23 +83,44 @@ where
 /// The principal AlChemy object. The `Soup` struct contains a set of
 /// lambda expressions, and rules for composing and filtering them.
 #[derive(Debug, Clone)]
-pub struct Soup<P, C, T, E> {
+pub struct Soup<P, C, T, E>
+where
+    P: Particle,
+{
     // All of these pub(crate)s here are hacky
     pub(crate) expressions: Vec<P>,
+    /// Number of copies of each distinct particle and of each species in `expressions`, kept
+    /// up to date by every change to it, so that counting species costs a pass over the
+    /// species rather than over the population.
+    pub(crate) counts: SpeciesCounts<P, P::Species>,
+    /// `n_collisions` at the time each expression was inserted, in the same order as
+    /// `expressions`.
+    pub(crate) births: Vec<usize>,
     pub(crate) n_collisions: usize,
+    /// Number of reactions attempted, successful or not.
+    pub(crate) n_attempts: usize,
     pub(crate) collider: C,
 
     pub(crate) maintain_constant_population_size: bool,
     pub(crate) discard_parents: bool,
+    pub(crate) max_species: Option<usize>,
+    pub(crate) max_population: Option<usize>,
 
     pub(crate) rng: ChaCha8Rng,
 
+    /// Kept only for `OscSink::poller`, since cloning every outcome is not free.
+    #[cfg(feature = "osc")]
+    pub(crate) last_reaction: Option<Result<T, E>>,
+
     // TODO: Figure out how to get rid of these horrible phantomdatas
     pub(crate) t: PhantomData<T>,
     pub(crate) e: PhantomData<E>,
 }
 
-pub struct Tape<P, C, T, E> {
+pub struct Tape<P, C, T, E>
+where
+    P: Particle,
+{
     soup: Soup<P, C, T, E>,
     history: Vec<Soup<P, C, T, E>>,
     polling_interval: usize,
@@ -54,7 +128,7 @@ pub struct Tape<P, C, T, E> {
 
 impl<P, C, T, E> Soup<P, C, T, E>
 where
-    P: Particle + Display + Clone,
+    P: Particle + Display + Clone + Eq + Hash,
     C: Collider<P, T, E> + Clone,
     T: Display + Clone + Residue<P>,
     E: Display + Clone + std::error::Error,
@@ -62,42 +136,300 @@ where
     /// Introduce all expressions in `expressions` into the soup, without
     /// reduction.
     pub fn perturb(&mut self, expressions: impl IntoIterator<Item = P>) {
-        self.expressions.extend(expressions)
+        self.extend(expressions);
+        debug_assert!(
+            self.counts_match(),
+            "species counts out of sync after perturb"
+        );
+    }
+
+    /// Remove every expression for which `keep` returns false, calling it once per expression
+    /// in order. Collision counts and the RNG are left untouched.
+    pub fn retain<F: FnMut(&P) -> bool>(&mut self, mut keep: F) {
+        let expressions = std::mem::take(&mut self.expressions);
+        let births = std::mem::take(&mut self.births);
+        let counts = &mut self.counts;
+        let collider = &self.collider;
+        (self.expressions, self.births) = expressions
+            .into_iter()
+            .zip(births)
+            .filter(|(p, _)| {
+                let kept = keep(p);
+                if !kept {
+                    counts.remove(p, &collider.species(p));
+                }
+                kept
+            })
+            .unzip();
+        debug_assert!(
+            self.counts_match(),
+            "species counts out of sync after retain"
+        );
+    }
+
+    /// Add `expressions`, born now, keeping `counts` in step.
+    fn extend(&mut self, expressions: impl IntoIterator<Item = P>) {
+        for p in expressions {
+            self.insert(p, self.n_collisions);
+        }
+    }
+
+    /// Add `p` with birth `birth`, keeping `counts` in step.
+    fn insert(&mut self, p: P, birth: usize) {
+        self.counts.add(&p, self.collider.species(&p));
+        self.expressions.push(p);
+        self.births.push(birth);
+    }
+
+    /// Whether `counts` matches a fresh count of `expressions`. This is a full pass over the
+    /// soup, meant for debug assertions around bulk changes; the per-reaction paths are kept
+    /// in step by `insert` and `swap_remove`.
+    fn counts_match(&self) -> bool {
+        fn matches<K: Eq + Hash>(fresh: impl Iterator<Item = K>, counts: &HashMap<K, u32>) -> bool {
+            let mut fresh_counts = HashMap::<K, u32>::new();
+            for k in fresh {
+                *fresh_counts.entry(k).or_default() += 1;
+            }
+            fresh_counts == *counts
+        }
+        matches(self.expressions.iter().cloned(), &self.counts.particles)
+            && matches(
+                self.expressions.iter().map(|p| self.collider.species(p)),
+                &self.counts.species,
+            )
+    }
+
+    /// Number of copies of each distinct particle in the soup.
+    pub fn particle_counts(&self) -> &HashMap<P, u32> {
+        &self.counts.particles
     }
 
-    /// Produce one atomic reaction on the soup.
+    /// Number of copies of each species in the soup, as given by `Collider::species`.
+    pub fn species_counts(&self) -> &HashMap<P::Species, u32> {
+        &self.counts.species
+    }
+
+    /// The Shannon entropy (base 10) of the distribution of species, read from a running sum
+    /// kept up to date by every change to the soup rather than recounted. For a `LambdaSoup`
+    /// this equals `population_entropy` up to rounding.
+    pub fn entropy_cached(&self) -> f32 {
+        let n = self.len() as f64;
+        if n == 0.0 {
+            return 0.0;
+        }
+        (n.log10() - self.counts.log_sum / n) as f32
+    }
+
+    /// Remove every particle whose `size` exceeds `limit`, returning how many were removed.
+    /// This is independent of any size limit the collider enforces while reacting: the collider
+    /// only fails reactions whose intermediate or final terms grow too large, whereas this
+    /// prunes particles already in the soup, such as those added with `perturb`.
+    pub fn prune_larger_than(&mut self, limit: usize) -> usize {
+        let before = self.len();
+        self.retain(|p| p.size() <= limit);
+        before - self.len()
+    }
+
+    /// Empty the soup and zero the collision counters, keeping the collider and configuration.
+    /// If `reseed` is true the RNG restarts from the soup's original seed, so a cleared soup
+    /// replays a fresh one exactly; otherwise it continues its current stream.
+    pub fn clear(&mut self, reseed: bool) {
+        self.expressions.clear();
+        self.births.clear();
+        self.counts.clear();
+        #[cfg(feature = "osc")]
+        {
+            self.last_reaction = None;
+        }
+        self.reset_collisions();
+        if reseed {
+            self.rng = ChaCha8Rng::from_seed(self.rng.get_seed());
+        }
+    }
+
+    /// Replace the collider, changing the chemistry from the next reaction on. The population,
+    /// RNG stream and counters are kept, so a run can continue in a new environment exactly as
+    /// it would have in the old one up to the switch. Species are recounted, since the new
+    /// collider may tell them apart differently.
+    pub fn set_collider(&mut self, collider: C) {
+        self.collider = collider;
+        let species = self.expressions.iter().map(|p| self.collider.species(p));
+        self.counts.recount_species(species);
+    }
+
+    /// Zero the collision and attempt counters. Expressions stay in the soup, with their ages
+    /// restarted.
+    pub fn reset_collisions(&mut self) {
+        self.n_collisions = 0;
+        self.n_attempts = 0;
+        self.births.fill(0);
+    }
+
+    /// Remove `n` expressions chosen uniformly at random. Each draw is uniform over the
+    /// expressions still in the soup, so the removed set is a uniform random `n`-subset no
+    /// matter how `swap_remove` reorders the survivors.
+    pub(crate) fn remove_random(&mut self, n: usize) {
+        for _ in 0..n {
+            let k = self.rng.gen_range(0..self.expressions.len());
+            self.swap_remove(k);
+        }
+    }
+
+    /// Remove the expression at index `i` along with its birth, by swapping in the last one.
+    pub(crate) fn swap_remove(&mut self, i: usize) -> (P, usize) {
+        let p = self.expressions.swap_remove(i);
+        self.counts.remove(&p, &self.collider.species(&p));
+        (p, self.births.swap_remove(i))
+    }
+
+    /// Produce one atomic reaction on the soup. If the soup `is_dead`, nothing is drawn or
+    /// counted as an attempt, and the collider's `not_enough_expressions` error is returned.
     pub fn react(&mut self) -> Result<T, E> {
+        self.react_observed(|_, _, _| ())
+    }
+
+    /// Like `react`, but also return the indices the two reactants held in the soup before the
+    /// reaction, in the order they were drawn. Runs with the same seed draw the same indices.
+    /// For a unary collider, both indices are those of the single reactant.
+    pub fn react_verbose(&mut self) -> (usize, usize, Result<T, E>) {
+        let mut drawn = (0, 0);
+        let result = self.react_observed(|slots, _, _| {
+            drawn = (slots[0], *slots.last().unwrap());
+        });
+        (drawn.0, drawn.1, result)
+    }
+
+    /// Like `react`, but call `observe` with the reactants' indices before the reaction, the
+    /// reactants, and the outcome, before the parents are returned to the soup. `observe` isn't
+    /// called if the soup `is_dead`.
+    pub(crate) fn react_observed<F>(&mut self, observe: F) -> Result<T, E>
+    where
+        F: FnOnce(&[usize], &[P], &Result<T, E>),
+    {
+        if self.is_dead() {
+            return Err(self.collider.not_enough_expressions());
+        }
         let n_expr = self.expressions.len();
 
-        // Remove two distinct expressions randomly from the soup
-        let i = self.rng.gen_range(0..n_expr);
-        let left = self.expressions.swap_remove(i);
+        // Remove `arity` distinct expressions randomly from the soup. `swap_remove` moves the
+        // last expression into the drawn index, so `moved` remembers where each moved one
+        // started, to report the indices as they were before the reaction.
+        let mut moved: Vec<(usize, usize)> = Vec::new();
+        let mut slots = Vec::with_capacity(self.collider.arity());
+        let (reactants, births): (Vec<P>, Vec<usize>) = (0..self.collider.arity())
+            .map(|k| {
+                let i = self.rng.gen_range(0..n_expr - k);
+                let slot_of = |pos: usize| {
+                    let m = moved.iter().rev().find(|(p, _)| *p == pos);
+                    m.map_or(pos, |(_, slot)| *slot)
+                };
+                slots.push(slot_of(i));
+                moved.push((i, slot_of(n_expr - k - 1)));
+                self.swap_remove(i)
+            })
+            .unzip();
 
-        let j = self.rng.gen_range(0..n_expr - 1);
-        let right = self.expressions.swap_remove(j);
+        let preserved = reactants
+            .iter()
+            .enumerate()
+            .map(|(k, p)| self.collider.preserves(k, p))
+            .collect::<Vec<_>>();
 
         // Add collision results to soup
-        let result = self.collider.collide(left.clone(), right.clone());
+        let result = self.collider.collide_all(reactants.clone());
+        self.n_attempts += 1;
 
         if let Ok(ref t) = result {
-            self.perturb(t.particles());
+            self.add_products(t);
+        }
+        observe(&slots, &reactants, &result);
 
-            // Remove additional expressions, if required.
-            if self.maintain_constant_population_size {
-                for _ in 0..t.count() {
-                    let k = self.rng.gen_range(0..self.expressions.len());
-                    self.expressions.swap_remove(k);
-                }
+        // Add removed parents back into the soup, if necessary. They keep their original age.
+        // A successful catalyzed reaction consumes the reactants that are not catalysts.
+        let catalyzed = result.is_ok() && preserved.contains(&true);
+        let discard = self.discard_parents || catalyzed;
+        for ((p, birth), preserved) in reactants.into_iter().zip(births).zip(preserved) {
+            if !discard || preserved {
+                self.insert(p, birth);
             }
         }
 
-        // Add removed parents back into the soup, if necessary
-        if !self.discard_parents {
-            self.expressions.push(left);
-            self.expressions.push(right);
+        if result.is_ok() {
+            self.cap_species();
+            self.cap_population();
+        }
+        #[cfg(feature = "osc")]
+        {
+            self.last_reaction = Some(result.clone());
+        }
+        result
+    }
+
+    /// Collide `left` with `right` directly instead of drawing two random expressions. The
+    /// particles need not be in the soup and are never removed from it; on success the products
+    /// are added under the same population rules as `react`.
+    pub fn react_pair(&mut self, left: P, right: P) -> Result<T, E> {
+        let result = self.collider.collide(left, right);
+        self.n_attempts += 1;
+        if let Ok(ref t) = result {
+            self.add_products(t);
+            self.cap_species();
+            self.cap_population();
+        }
+        #[cfg(feature = "osc")]
+        {
+            self.last_reaction = Some(result.clone());
+        }
+        result
+    }
+
+    /// Count a successful collision and add its products, removing random expressions to keep
+    /// the population constant if required.
+    fn add_products(&mut self, t: &T) {
+        self.n_collisions += 1;
+        self.extend(t.particles());
+
+        if self.maintain_constant_population_size {
+            self.remove_random(t.count());
+        }
+    }
+
+    /// If the soup holds more than `max_population` particles, remove random ones until it
+    /// doesn't.
+    fn cap_population(&mut self) {
+        if let Some(max_population) = self.max_population {
+            self.remove_random(self.len().saturating_sub(max_population));
         }
+    }
 
-        result.clone()
+    /// If the soup holds more than `max_species` species, remove every copy of the least
+    /// frequent ones until it doesn't. Ties go against the species that entered the soup's
+    /// order last, so the result is deterministic. This is a lossy pressure that changes the
+    /// dynamics, and it shrinks the population even when it is meant to be constant. Only
+    /// reactions that push the soup past the cap pay for a pass over it.
+    fn cap_species(&mut self) {
+        let Some(max_species) = self.max_species else {
+            return;
+        };
+        if self.counts.species.len() <= max_species {
+            return;
+        }
+        let keys = self
+            .expressions
+            .iter()
+            .map(|p| self.collider.species(p))
+            .collect::<Vec<_>>();
+        let mut seen = HashSet::new();
+        let mut species = keys.iter().filter(|k| seen.insert(*k)).collect::<Vec<_>>();
+        species.sort_by_key(|k| std::cmp::Reverse(self.counts.species[*k]));
+        let rare = species
+            .split_off(max_species)
+            .into_iter()
+            .cloned()
+            .collect::<HashSet<_>>();
+        // `retain` visits the expressions in order, once each
+        let mut keys = keys.into_iter();
+        self.retain(|_| !rare.contains(&keys.next().unwrap()));
     }
 
     fn log_message_from_reaction(reaction: &Result<T, E>) -> String {
@@ -107,25 +439,133 @@ where
         }
     }
 
-    /// Simulate the soup for `n` collisions. If `log` is set, then print
-    /// out a log message for each reaction. Returns the number of successful reactions
-    /// (the fraction of failed reactions).
+    /// Simulate the soup for `n` collisions. If `log` is set, then log a message at `info`
+    /// level for each reaction. Returns the number of successful reactions
+    /// (the fraction of failed reactions). Stops early if the soup `is_dead`, which the return
+    /// value doesn't show: check `is_dead()` afterwards to tell a short run from a full one.
+    /// The other `simulate_*` methods, `snapshots` and `simulate_and_record` stop the same
+    /// way.
     pub fn simulate_for(&mut self, n: usize, log: bool) -> usize {
         let mut n_successes = 0;
         for i in 0..n {
+            if self.is_dead() {
+                break;
+            }
             let reaction = self.react();
             if reaction.is_ok() {
                 n_successes += 1;
             }
 
             if log {
-                let message = Self::log_message_from_reaction(&reaction);
-                println!("reaction {:?} {}", i, message)
+                info!(
+                    "reaction {:?} {}",
+                    i,
+                    Self::log_message_from_reaction(&reaction)
+                );
             }
         }
         n_successes
     }
 
+    /// Simulate the soup for `n` collisions, calling `injector` after every `injection_interval`
+    /// of them to add expressions, so that inflow is spread over the run instead of arriving in
+    /// bursts between separate runs. The soup's RNG isn't exposed to the injector; only the
+    /// soup's own helpers draw from it (e.g. `add_lambda_expressions_shuffled`,
+    /// `perturb_lambda_expressions` and `inject_from_generator`), which keeps the schedule
+    /// reproducible. Returns the number of successful reactions. Stops early if the soup
+    /// `is_dead`, before the injector has a chance to refill it.
+    pub fn simulate_with_injection<F>(
+        &mut self,
+        n: usize,
+        injection_interval: usize,
+        mut injector: F,
+    ) -> usize
+    where
+        F: FnMut(&mut Self),
+    {
+        assert!(
+            injection_interval > 0,
+            "injection interval must be positive"
+        );
+        let mut n_successes = 0;
+        for i in 0..n {
+            if self.is_dead() {
+                break;
+            }
+            if self.react().is_ok() {
+                n_successes += 1;
+            }
+            if (i + 1) % injection_interval == 0 {
+                injector(self);
+            }
+        }
+        n_successes
+    }
+
+    /// Like `simulate_for`, but return `(successes, total_products)`, where `total_products`
+    /// sums `count()` over the residues of successful reactions. This differs from the number
+    /// of successes whenever a reaction yields more than one particle.
+    pub fn simulate_for_counting(&mut self, n: usize, log: bool) -> (usize, usize) {
+        let mut n_successes = 0;
+        let mut n_products = 0;
+        for i in 0..n {
+            if self.is_dead() {
+                break;
+            }
+            let reaction = self.react();
+            if let Ok(ref t) = reaction {
+                n_successes += 1;
+                n_products += t.count();
+            }
+
+            if log {
+                info!(
+                    "reaction {:?} {}",
+                    i,
+                    Self::log_message_from_reaction(&reaction)
+                );
+            }
+        }
+        (n_successes, n_products)
+    }
+
+    /// Simulate the soup for `n` collisions, recording the fraction of successful reactions
+    /// among the last `window` after reactions `0`, `interval`, `2 * interval`, ... During
+    /// warm-up, before `window` reactions have run, the rate is taken over the partial window
+    /// of every reaction so far, so the first point is always `0.0` or `1.0`. Panics if
+    /// `window` or `interval` is zero.
+    pub fn simulate_with_rate(&mut self, n: usize, window: usize, interval: usize) -> Vec<f32> {
+        assert!(window > 0, "window must be positive");
+        assert!(interval > 0, "interval must be positive");
+        let mut outcomes = VecDeque::with_capacity(window);
+        let mut n_successes = 0;
+        let mut rates = Vec::new();
+        for i in 0..n {
+            if self.is_dead() {
+                break;
+            }
+            let success = self.react().is_ok();
+            if outcomes.len() == window && outcomes.pop_front() == Some(true) {
+                n_successes -= 1;
+            }
+            outcomes.push_back(success);
+            if success {
+                n_successes += 1;
+            }
+
+            if (i % interval) == 0 {
+                rates.push(n_successes as f32 / outcomes.len() as f32);
+            }
+        }
+        rates
+    }
+
+    /// Simulate the soup for `n` collisions, calling `poller` after reactions `0`,
+    /// `polling_interval`, `2 * polling_interval`, ..., and once more on the final state if
+    /// that wasn't just polled. A full run thus produces `ceil(n / polling_interval)` samples,
+    /// plus one when `(n - 1) % polling_interval != 0`, and a single sample for `n == 0`. A
+    /// soup that dies stops early, with its final state polled the same way. If `log` is set,
+    /// then log a message at `info` level for each reaction.
     pub fn simulate_and_poll<F, R>(
         &mut self,
         n: usize,
@@ -137,19 +577,34 @@ where
         F: Fn(&Self) -> R,
     {
         let mut data: Vec<R> = Vec::new();
+        let mut polled_final_state = false;
         for i in 0..n {
+            if self.is_dead() {
+                break;
+            }
             let reaction = self.react();
-            if (i % polling_interval) == 0 {
+            polled_final_state = (i % polling_interval) == 0;
+            if polled_final_state {
                 data.push(poller(self))
             }
             if log {
-                let message = Self::log_message_from_reaction(&reaction);
-                println!("reaction {:?} {}", i, message)
+                info!(
+                    "reaction {:?} {}",
+                    i,
+                    Self::log_message_from_reaction(&reaction)
+                );
             }
         }
+        if !polled_final_state {
+            data.push(poller(self))
+        }
         data
     }
 
+    /// Like `simulate_and_poll`, but stop as soon as `killpoller` returns `true` along with its
+    /// datum. The final state is polled only if the run wasn't stopped, so a run that is never
+    /// stopped produces the same `ceil(n / polling_interval)` samples, plus one when
+    /// `(n - 1) % polling_interval != 0`, or a single sample for `n == 0`.
     pub fn simulate_and_poll_with_killer<F, R>(
         &mut self,
         n: usize,
@@ -161,9 +616,14 @@ where
         F: Fn(&Self) -> (R, bool),
     {
         let mut data: Vec<R> = Vec::new();
+        let mut polled_final_state = false;
         for i in 0..n {
+            if self.is_dead() {
+                break;
+            }
             let reaction = self.react();
-            if (i % polling_interval) == 0 {
+            polled_final_state = (i % polling_interval) == 0;
+            if polled_final_state {
                 let (datum, should_kill) = killpoller(self);
                 data.push(datum);
                 if should_kill {
@@ -171,16 +631,62 @@ where
                 };
             }
             if log {
-                let message = Self::log_message_from_reaction(&reaction);
-                println!("reaction {:?} {}", i, message)
+                info!(
+                    "reaction {:?} {}",
+                    i,
+                    Self::log_message_from_reaction(&reaction)
+                );
             }
         }
+        if !polled_final_state {
+            data.push(killpoller(self).0)
+        }
         data
     }
 
-    /// Simulate the soup for `n` collisions, recording the state of the soup every
-    /// `polling_interval` reactions. If `log` is set, then print out a log message for each
-    /// reaction
+    /// Lazily simulate the soup for `n` collisions, yielding `(i, f(soup))` after reactions
+    /// `i = 0`, `polling_interval`, `2 * polling_interval`, ... Reactions only run as the
+    /// iterator is advanced, and nothing but the projections is kept, so this is the streaming
+    /// counterpart of `simulate_and_record`. Dropping the iterator early leaves the soup after
+    /// the last yielded snapshot. If `log` is set, then log a message at `info` level for each
+    /// reaction.
+    pub fn snapshots<'a, F, R>(
+        &'a mut self,
+        n: usize,
+        polling_interval: usize,
+        log: bool,
+        f: F,
+    ) -> impl Iterator<Item = (usize, R)> + 'a
+    where
+        F: Fn(&Self) -> R + 'a,
+        R: 'a,
+    {
+        let mut reactions = 0..n;
+        std::iter::from_fn(move || {
+            for i in reactions.by_ref() {
+                if self.is_dead() {
+                    return None;
+                }
+                let reaction = self.react();
+                if log {
+                    info!(
+                        "reaction {:?} {}",
+                        i,
+                        Self::log_message_from_reaction(&reaction)
+                    );
+                }
+                if (i % polling_interval) == 0 {
+                    return Some((i, f(self)));
+                }
+            }
+            None
+        })
+    }
+
+    /// Simulate the soup for `n` collisions, recording the state of the soup after every
+    /// `polling_interval`-th reaction, so that snapshot `k` of the history is the state after
+    /// reaction `k * polling_interval`. The final state is kept apart, as `Tape::final_state`.
+    /// If `log` is set, then log a message at `info` level for each reaction
     pub fn simulate_and_record(
         &mut self,
         n: usize,
@@ -189,13 +695,19 @@ where
     ) -> Tape<P, C, T, E> {
         let mut history: Vec<Self> = Vec::new();
         for i in 0..n {
+            if self.is_dead() {
+                break;
+            }
             let reaction = self.react();
             if (i % polling_interval) == 0 {
                 history.push(self.clone())
             }
             if log {
-                let message = Self::log_message_from_reaction(&reaction);
-                println!("reaction {:?} {}", i, message)
+                info!(
+                    "reaction {:?} {}",
+                    i,
+                    Self::log_message_from_reaction(&reaction)
+                );
             }
         }
 
@@ -218,6 +730,14 @@ where
         self.expressions.iter()
     }
 
+    /// Draw `min(k, len())` distinct expressions uniformly at random, without replacement,
+    /// using `rng`. The soup and its own generator are untouched, so polling a sample does not
+    /// change the course of a run. Statistics of the sample, such as its entropy, are
+    /// estimates of those of the whole population, not exact values.
+    pub fn take_sample(&self, k: usize, rng: &mut impl Rng) -> Vec<P> {
+        self.expressions.choose_multiple(rng, k).cloned().collect()
+    }
+
     /// Get the number of expressions in the soup.
     pub fn len(&self) -> usize {
         self.expressions.len()
@@ -227,10 +747,162 @@ where
         self.expressions.is_empty()
     }
 
+    /// Whether the soup holds too few expressions for the collider to react, as after
+    /// aggressive trimming or with `discard_parents` and failed reactions. `react` fails on a
+    /// dead soup, so the `simulate_*` methods, `snapshots` and `simulate_and_record` check
+    /// this before every reaction and stop early once it holds. Check it after a run to tell a
+    /// soup that ran out of expressions from one that ran its course.
+    pub fn is_dead(&self) -> bool {
+        self.len() < self.collider.arity()
+    }
+
     /// Get the number of successful collisions
     pub fn collisions(&self) -> usize {
         self.n_collisions
     }
+
+    /// Get the number of reactions attempted, including failed ones
+    pub fn attempts(&self) -> usize {
+        self.n_attempts
+    }
+
+    /// Get the fraction of attempted reactions that succeeded, or `0.0` before any attempt.
+    pub fn success_rate(&self) -> f32 {
+        if self.n_attempts == 0 {
+            return 0.0;
+        }
+        self.n_collisions as f32 / self.n_attempts as f32
+    }
+
+    /// Get the fraction of attempted reactions that failed, or `0.0` before any attempt.
+    pub fn failure_rate(&self) -> f32 {
+        if self.n_attempts == 0 {
+            return 0.0;
+        }
+        1.0 - self.success_rate()
+    }
+
+    /// Get the age of every expression, in successful collisions since it was inserted, in the
+    /// same order as `expressions`.
+    pub fn expression_ages(&self) -> impl Iterator<Item = usize> + '_ {
+        self.births.iter().map(|b| self.n_collisions - b)
+    }
+
+    /// Count the expressions of each age.
+    pub fn age_histogram(&self) -> BTreeMap<usize, usize> {
+        histogram(self.expression_ages())
+    }
+
+    /// Count the expressions by the value of `f`, e.g. `soup.histogram_of(|p| p.size())`.
+    pub fn histogram_of<K: Ord, F: Fn(&P) -> K>(&self, f: F) -> BTreeMap<K, usize> {
+        histogram(self.expressions.iter().map(f))
+    }
+
+    /// Count the expressions of each size.
+    pub fn size_histogram(&self) -> BTreeMap<usize, usize> {
+        self.histogram_of(|p| p.size())
+    }
+
+    /// Get the mean age of all expressions, or `0.0` for an empty soup.
+    pub fn mean_age(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        self.expression_ages().sum::<usize>() as f32 / self.len() as f32
+    }
+
+    /// Get the outcome of the most recent reaction, or `None` if the soup hasn't reacted yet.
+    #[cfg(feature = "osc")]
+    pub fn last_reaction(&self) -> Option<&Result<T, E>> {
+        self.last_reaction.as_ref()
+    }
+}
+
+/// Copies of each distinct particle and of each species `S`, with the running sum `Σ c log10 c`
+/// over the species' copy counts `c` from which the population entropy follows without a pass
+/// over the species.
+#[derive(Debug, Clone)]
+pub(crate) struct SpeciesCounts<P, S> {
+    particles: HashMap<P, u32>,
+    species: HashMap<S, u32>,
+    log_sum: f64,
+}
+
+impl<P, S> Default for SpeciesCounts<P, S> {
+    fn default() -> Self {
+        SpeciesCounts {
+            particles: HashMap::new(),
+            species: HashMap::new(),
+            log_sum: 0.0,
+        }
+    }
+}
+
+/// `c log10 c`, the contribution of a species with `c` copies to `SpeciesCounts::log_sum`.
+fn c_log_c(c: u32) -> f64 {
+    match c {
+        0 => 0.0,
+        c => c as f64 * (c as f64).log10(),
+    }
+}
+
+impl<P: Eq + Hash + Clone, S: Eq + Hash> SpeciesCounts<P, S> {
+    /// Count one more copy of `p`, of species `s`.
+    fn add(&mut self, p: &P, s: S) {
+        match self.particles.get_mut(p) {
+            Some(count) => *count += 1,
+            None => {
+                self.particles.insert(p.clone(), 1);
+            }
+        }
+        self.add_species(s);
+    }
+
+    fn add_species(&mut self, s: S) {
+        let count = self.species.entry(s).or_insert(0);
+        self.log_sum += c_log_c(*count + 1) - c_log_c(*count);
+        *count += 1;
+    }
+
+    /// Take one copy of `p`, of species `s`, off, forgetting either once none are left.
+    fn remove(&mut self, p: &P, s: &S) {
+        fn take_one<K: Eq + Hash>(counts: &mut HashMap<K, u32>, k: &K) -> u32 {
+            let count = counts
+                .get_mut(k)
+                .expect("removed particle was never counted");
+            *count -= 1;
+            let left = *count;
+            if left == 0 {
+                counts.remove(k);
+            }
+            left
+        }
+        take_one(&mut self.particles, p);
+        let left = take_one(&mut self.species, s);
+        self.log_sum += c_log_c(left) - c_log_c(left + 1);
+    }
+
+    /// Replace the species counts with a count of `species`, keeping the particle counts.
+    fn recount_species(&mut self, species: impl Iterator<Item = S>) {
+        self.species.clear();
+        self.log_sum = 0.0;
+        species.for_each(|s| self.add_species(s));
+    }
+
+    fn clear(&mut self) {
+        self.particles.clear();
+        self.species.clear();
+        self.log_sum = 0.0;
+    }
+}
+
+/// Count the occurrences of each key.
+fn histogram<K: Ord>(keys: impl Iterator<Item = K>) -> BTreeMap<K, usize> {
+    let mut histogram = BTreeMap::new();
+    for key in keys {
+        *histogram.entry(key).or_default() += 1;
+    }
+    histogram
 }
 
 impl<P, C, T, E> Tape<P, C, T, E>
@@ -251,4 +923,510 @@ where
     pub fn polling_interval(&self) -> usize {
         self.polling_interval
     }
+
+    /// Apply `f` to every recorded snapshot, pairing each result with the snapshot's index in
+    /// `history`. Multiply the index by `polling_interval` to get the reaction it was taken at.
+    pub fn to_series<F, R>(&self, f: F) -> Vec<(usize, R)>
+    where
+        F: Fn(&Soup<P, C, T, E>) -> R,
+    {
+        self.history.iter().map(&f).enumerate().collect()
+    }
+}
+
+/// Sends reactions to a SuperCollider server as OSC messages over UDP. Only available with the
+/// `osc` feature.
+#[cfg(feature = "osc")]
+pub struct OscSink {
+    socket: UdpSocket,
+    address: String,
+}
+
+#[cfg(feature = "osc")]
+impl OscSink {
+    /// Open a sink that sends to `target` (e.g. `"127.0.0.1:57120"` for sclang) under the OSC
+    /// address pattern `address` (e.g. `"/alchemy/reaction"`).
+    pub fn new(target: impl ToSocketAddrs, address: &str) -> io::Result<Self> {
+        let target = target.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no address to send OSC to")
+        })?;
+        let local = if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(target)?;
+        Ok(OscSink {
+            socket,
+            address: address.to_string(),
+        })
+    }
+
+    /// Send a single OSC message with `args` as float arguments.
+    pub fn send(&self, args: &[f32]) -> io::Result<()> {
+        self.socket
+            .send(&osc_packet(&self.address, args))
+            .map(|_| ())
+    }
+
+    /// Make a poller for `Soup::simulate_and_poll` that sends one message for the most recent
+    /// reaction if it succeeded, with arguments given by `to_args`. Use a polling interval of `1`
+    /// to hear every reaction.
+    pub fn poller<'a, P, C, T, E, F>(
+        &'a self,
+        to_args: F,
+    ) -> impl Fn(&Soup<P, C, T, E>) -> io::Result<()> + 'a
+    where
+        F: Fn(&T) -> Vec<f32> + 'a,
+        P: Particle + 'a,
+        C: 'a,
+        T: 'a,
+        E: 'a,
+    {
+        move |soup| match soup.last_reaction {
+            Some(Ok(ref t)) => self.send(&to_args(t)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// An OSC message to `address` with `args` as float arguments.
+#[cfg(feature = "osc")]
+fn osc_packet(address: &str, args: &[f32]) -> Vec<u8> {
+    let mut packet = Vec::new();
+    push_osc_string(&mut packet, address);
+    push_osc_string(&mut packet, &format!(",{}", "f".repeat(args.len())));
+    for arg in args {
+        packet.extend_from_slice(&arg.to_be_bytes());
+    }
+    packet
+}
+
+/// Append a null-terminated string padded to a multiple of four bytes, as OSC requires.
+#[cfg(feature = "osc")]
+fn push_osc_string(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(s.as_bytes());
+    packet.push(0);
+    packet.resize(packet.len().next_multiple_of(4), 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        abs,
+        combinators::{I, K, S},
+        IntoChurchNum, Term,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::config::{self, ConfigSeed};
+    use crate::lambda::recursive::{
+        AlchemyCollider, LambdaCollisionError, LambdaParticle, LambdaSoup,
+    };
+
+    fn test_soup() -> LambdaSoup {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            seed: ConfigSeed::new([1; 32]),
+            ..config::Reactor::new()
+        });
+        soup.add_lambda_expressions([S(), K(), I()].into_iter().cycle().take(30));
+        soup
+    }
+
+    #[test]
+    fn ages_track_insertion() {
+        let mut soup = test_soup();
+        assert_eq!(soup.age_histogram().get(&0), Some(&30));
+
+        assert_eq!(soup.failure_rate(), 0.0);
+        let n_successes = soup.simulate_for(200, false);
+        assert_eq!(soup.collisions(), n_successes);
+        assert_eq!(soup.attempts(), 200);
+        assert_eq!(soup.success_rate(), n_successes as f32 / 200.0);
+        assert_eq!(soup.expression_ages().count(), soup.len());
+        assert!(soup.expression_ages().all(|age| age <= n_successes));
+        assert_eq!(soup.age_histogram().values().sum::<usize>(), soup.len());
+    }
+
+    #[test]
+    fn reseeded_clear_replays_run() {
+        let mut soup = test_soup();
+        soup.simulate_for(100, false);
+        let first = soup
+            .expressions()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+
+        soup.clear(true);
+        assert!(soup.is_empty());
+        assert_eq!(soup.collisions(), 0);
+        soup.add_lambda_expressions([S(), K(), I()].into_iter().cycle().take(30));
+        soup.simulate_for(100, false);
+        let second = soup
+            .expressions()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn react_pair_adds_products() {
+        let mut soup = test_soup();
+        let (k, i) = (soup.expressions[1].clone(), soup.expressions[2].clone());
+
+        let products = soup.react_pair(k, i).unwrap();
+        assert_eq!(products.results.len(), 1);
+        assert_eq!(soup.collisions(), 1);
+        assert_eq!(soup.len(), 30);
+    }
+
+    #[test]
+    fn full_window_rate_matches_simulate_for() {
+        let mut soup = test_soup();
+        let mut replay = soup.clone();
+
+        let rates = soup.simulate_with_rate(200, 200, 199);
+        let n_successes = replay.simulate_for(200, false);
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[1], n_successes as f32 / 200.0);
+    }
+
+    #[test]
+    fn verbose_reactions_report_drawn_indices() {
+        let mut soup = test_soup();
+        for _ in 0..50 {
+            let before = soup.expressions().cloned().collect::<Vec<_>>();
+            let mut twin = soup.clone();
+            let (i, j, result) = soup.react_verbose();
+            let twin_result = twin.react_observed(|slots, reactants, _| {
+                assert_eq!(slots, [i, j]);
+                assert_eq!(reactants, [before[i].clone(), before[j].clone()]);
+            });
+            assert_ne!(i, j);
+            assert_eq!(result.is_ok(), twin_result.is_ok());
+        }
+    }
+
+    #[cfg(feature = "osc")]
+    #[test]
+    fn osc_packets_are_padded() {
+        let packet = super::osc_packet("/ab", &[1.0]);
+        let mut expected = b"/ab\0,f\0\0".to_vec();
+        expected.extend_from_slice(&1f32.to_be_bytes());
+        assert_eq!(packet, expected);
+
+        // A string whose length is a multiple of four still gets a full word of padding
+        let packet = super::osc_packet("/abc", &[]);
+        assert_eq!(packet, b"/abc\0\0\0\0,\0\0\0");
+    }
+
+    #[test]
+    fn random_removal_is_uniform() {
+        const TRIALS: usize = 4000;
+        let originals = (0..10).map(|n| n.into_church()).collect::<Vec<Term>>();
+        let mut removed = [0usize; 10];
+        for trial in 0..TRIALS {
+            let mut soup = LambdaSoup::new();
+            soup.rng = ChaCha8Rng::seed_from_u64(trial as u64);
+            soup.add_lambda_expressions(originals.iter().cloned());
+            soup.remove_random(3);
+            for (i, t) in originals.iter().enumerate() {
+                if !soup.lambda_expressions().any(|e| e == t) {
+                    removed[i] += 1;
+                }
+            }
+        }
+
+        // Each expression is removed with probability 0.3; allow five standard deviations
+        let expected = 0.3 * TRIALS as f64;
+        let tolerance = 5.0 * (TRIALS as f64 * 0.3 * 0.7).sqrt();
+        for count in removed {
+            assert!((count as f64 - expected).abs() < tolerance, "{removed:?}");
+        }
+    }
+
+    #[test]
+    fn extinction_is_detected() {
+        let mut soup = test_soup();
+        assert_eq!(soup.simulate_until_extinct(&7.into_church(), 10), Some(0));
+
+        let mut replay = soup.clone();
+        match soup.simulate_until_extinct(&K(), 2000) {
+            Some(t) => {
+                assert_eq!(soup.population_of(&K()), 0);
+                replay.simulate_for(t - 1, false);
+                assert!(replay.population_of(&K()) > 0);
+            }
+            None => assert!(soup.population_of(&K()) > 0),
+        }
+    }
+
+    #[test]
+    fn catalysts_survive_on_the_left() {
+        let mut n_catalyzed = 0;
+        for seed in 0..16 {
+            let mut soup = LambdaSoup::from_config(&config::Reactor {
+                discard_parents: true,
+                maintain_constant_population_size: false,
+                catalysts: vec![String::from(r"\x.\y.x")],
+                seed: ConfigSeed::new([seed; 32]),
+                ..config::Reactor::new()
+            });
+            soup.add_lambda_expressions([K(), S()]);
+            let (left, _, _) = soup.react_verbose();
+            // K is kept on the left, whether K S reacts or not, and S is consumed
+            let n_k = usize::from(left == 0);
+            assert_eq!(soup.population_of(&K()), n_k);
+            assert_eq!(soup.population_of(&S()), 0);
+            n_catalyzed += n_k;
+        }
+        assert!(n_catalyzed > 0);
+    }
+
+    #[test]
+    fn catalysts_consume_without_discard_parents() {
+        let mut n_catalyzed = 0;
+        for seed in 0..16 {
+            let mut soup = LambdaSoup::from_config(&config::Reactor {
+                maintain_constant_population_size: false,
+                catalysts: vec![String::from(r"\x.\y.x")],
+                seed: ConfigSeed::new([seed; 32]),
+                ..config::Reactor::new()
+            });
+            soup.add_lambda_expressions([K(), S()]);
+            let (left, _, result) = soup.react_verbose();
+            assert!(result.is_ok());
+            // K S consumes S, while S K is uncatalyzed and keeps both parents
+            let catalyzed = left == 0;
+            assert_eq!(soup.population_of(&K()), 1);
+            assert_eq!(soup.population_of(&S()), usize::from(!catalyzed));
+            n_catalyzed += usize::from(catalyzed);
+        }
+        assert!(n_catalyzed > 0);
+    }
+
+    #[test]
+    fn swapped_rules_keep_the_run_going() {
+        let mut soup = test_soup();
+        soup.simulate_for(50, false);
+        let mut replay = soup.clone();
+        soup.set_rules(vec![String::from(r"\x.\y.x y")]);
+        soup.simulate_for(50, false);
+        replay.simulate_for(50, false);
+        assert!(soup.expressions().eq(replay.expressions()));
+        assert_eq!(soup.collisions(), replay.collisions());
+
+        soup.set_rules(vec![String::from(r"\x.\y.y x")]);
+        assert_eq!(soup.collider.to_config().rules, [r"\x.\y.y x"]);
+        assert_eq!(
+            (soup.len(), soup.rng.clone()),
+            (replay.len(), replay.rng.clone())
+        );
+        // I K is a copy of K forward, and K I = \y.I backward
+        let residue = soup.react_pair(LambdaParticle::new(I()), LambdaParticle::new(K()));
+        assert_eq!(residue.unwrap().results[0].expr, abs(I()));
+    }
+
+    #[test]
+    fn injection_follows_its_interval() {
+        let mut soup = test_soup();
+        let mut injected_at = Vec::new();
+        soup.simulate_with_injection(95, 10, |s| {
+            injected_at.push(s.attempts());
+            s.add_lambda_expressions([I()]);
+        });
+        assert_eq!(injected_at, (1..=9).map(|k| 10 * k).collect::<Vec<_>>());
+        assert_eq!(soup.attempts(), 95);
+    }
+
+    #[test]
+    fn samples_leave_soup_alone() {
+        let soup = test_soup();
+        let before = soup.clone();
+        let mut rng = ChaCha8Rng::from_seed([2; 32]);
+        let sample = soup.take_sample(10, &mut rng);
+        assert_eq!(sample.len(), 10);
+        assert!(sample.iter().all(|p| soup.population_of(&p.expr) > 0));
+        assert_eq!(soup.take_sample(100, &mut rng).len(), soup.len());
+        assert!(soup.expressions().eq(before.expressions()));
+        assert_eq!(soup.rng, before.rng);
+    }
+
+    #[test]
+    fn dead_soups_stop_early() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor::new());
+        soup.add_lambda_expressions([K()]);
+        assert!(soup.is_dead());
+        assert_eq!(
+            soup.react().unwrap_err(),
+            LambdaCollisionError::NotEnoughExpressions
+        );
+        assert_eq!(soup.simulate_for(10, false), 0);
+        assert_eq!(soup.attempts(), 0);
+        assert_eq!(soup.simulate_and_poll(10, 2, false, |s| s.len()), [1]);
+        assert!(soup.simulate_until_novel(10).is_none());
+
+        let mut soup = test_soup();
+        soup.retain(|_| false);
+        assert!(soup.is_dead());
+        assert_eq!(soup.snapshots(10, 1, false, |s| s.len()).count(), 0);
+    }
+
+    #[test]
+    fn novelty_is_detected() {
+        let mut soup = test_soup();
+        let mut replay = soup.clone();
+        let (t, novel) = soup.simulate_until_novel(100).unwrap();
+        assert_eq!(replay.population_of(&novel), 0);
+        replay.simulate_for(t - 1, false);
+        assert_eq!(replay.population_of(&novel), 0);
+    }
+
+    #[test]
+    fn species_cap_bounds_diversity() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            seed: ConfigSeed::new([1; 32]),
+            max_species: Some(3),
+            ..config::Reactor::new()
+        });
+        soup.add_lambda_expressions([S(), K(), I()].into_iter().cycle().take(30));
+        soup.simulate_for(200, false);
+        assert!(soup.expression_counts().len() <= 3);
+    }
+
+    #[test]
+    fn species_counts_follow_every_change() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            seed: ConfigSeed::new([1; 32]),
+            maintain_constant_population_size: false,
+            max_population: Some(45),
+            ..config::Reactor::new()
+        });
+        soup.add_lambda_expressions([S(), K(), I()].into_iter().cycle().take(30));
+        soup.add_test_expressions([K()]);
+        assert_eq!(soup.particle_counts().len(), 4);
+        assert_eq!(soup.species_count(), 3);
+
+        soup.simulate_for(100, false);
+        assert!(soup.counts_match());
+        soup.prune_larger_than(5);
+        soup.perturb_lambda_expressions(5, [S()].into_iter().cycle());
+        assert!(soup.counts_match());
+        assert_eq!(soup.species_count(), soup.unique_expressions().len());
+
+        soup.clear(false);
+        assert!(soup.particle_counts().is_empty());
+    }
+
+    #[test]
+    fn cached_entropy_matches_recount() {
+        let mut soup = test_soup();
+        assert!((soup.entropy_cached() - soup.population_entropy()).abs() < 1e-5);
+        for _ in 0..20 {
+            soup.simulate_for(50, false);
+            assert!((soup.entropy_cached() - soup.population_entropy()).abs() < 1e-5);
+        }
+        soup.retain(|p| p.expr != K());
+        assert!((soup.entropy_cached() - soup.population_entropy()).abs() < 1e-5);
+        soup.clear(false);
+        assert_eq!(soup.entropy_cached(), 0.0);
+    }
+
+    #[test]
+    fn species_merge_eta_variants_and_test_particles() {
+        let eta_cfg = config::Reactor {
+            seed: ConfigSeed::new([1; 32]),
+            use_eta: true,
+            ..config::Reactor::new()
+        };
+        let mut soup = LambdaSoup::from_config(&eta_cfg);
+        let eta_k = abs(Term::App(Box::new((K(), Term::Var(1)))));
+        soup.add_lambda_expressions([K(), eta_k, S(), S()]);
+        soup.add_test_expressions([S()]);
+        assert_eq!(soup.particle_counts().len(), 4);
+        assert_eq!(soup.species_count(), 2);
+        assert_eq!(soup.species_counts().values().sum::<u32>(), 5);
+        assert!((soup.entropy_cached() - soup.population_entropy()).abs() < 1e-5);
+
+        // Without eta, `K` and `\x. K x` part ways
+        soup.set_collider(AlchemyCollider::from_config(&config::Reactor {
+            use_eta: false,
+            ..eta_cfg
+        }));
+        assert!(soup.counts_match());
+        assert_eq!(soup.species_count(), 3);
+        assert!((soup.entropy_cached() - soup.population_entropy()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn population_cap_stops_growth() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            seed: ConfigSeed::new([1; 32]),
+            maintain_constant_population_size: false,
+            max_population: Some(40),
+            ..config::Reactor::new()
+        });
+        soup.add_lambda_expressions([S(), K(), I()].into_iter().cycle().take(30));
+        let n_successes = soup.simulate_for(200, false);
+        assert!(n_successes > 10);
+        assert_eq!(soup.len(), 40);
+    }
+
+    #[test]
+    fn trace_has_one_line_per_reaction() {
+        let mut soup = test_soup();
+        let mut trace = Vec::new();
+        let n_successes = soup.simulate_for_trace(50, &mut trace).unwrap();
+
+        let lines = String::from_utf8(trace).unwrap();
+        let records = lines
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 50);
+        assert_eq!(
+            records.iter().filter(|r| r["outcome"] == "ok").count(),
+            n_successes
+        );
+    }
+
+    #[test]
+    fn snapshots_match_simulate_and_poll() {
+        let mut polled = test_soup();
+        let expected = polled.simulate_and_poll(95, 10, false, |s| s.collisions());
+
+        let mut streamed = test_soup();
+        let snapshots = streamed
+            .snapshots(95, 10, false, |s| s.collisions())
+            .collect::<Vec<_>>();
+        let (indices, collisions): (Vec<usize>, Vec<usize>) = snapshots.into_iter().unzip();
+        assert_eq!(indices, (0..95).step_by(10).collect::<Vec<_>>());
+        // `simulate_and_poll` also polls the final state
+        assert_eq!(collisions, expected[..expected.len() - 1]);
+    }
+
+    #[test]
+    fn killer_polls_like_simulate_and_poll() {
+        let mut polled = test_soup();
+        let expected = polled.simulate_and_poll(95, 10, false, |s| s.collisions());
+        let mut killable = test_soup();
+        let polls =
+            killable.simulate_and_poll_with_killer(95, 10, false, |s| (s.collisions(), false));
+        assert_eq!(polls, expected);
+
+        let mut killed = test_soup();
+        let polls = killed.simulate_and_poll_with_killer(95, 10, false, |s| (s.attempts(), true));
+        assert_eq!(polls, [1]);
+    }
+
+    #[test]
+    fn poll_counts_follow_the_interval() {
+        for (n, expected) in [(0, 1), (1, 1), (5, 2), (10, 3), (11, 3)] {
+            let polls = test_soup().simulate_and_poll(n, 5, false, |s| s.attempts());
+            assert_eq!(polls.len(), expected, "{} reactions", n);
+            assert_eq!(polls.last(), Some(&n));
+        }
+    }
 }
@@ -1,9 +1,16 @@
-use std::cmp::Ord;
+use std::cmp::{Ord, Reverse};
 use std::{fmt, num::ParseIntError};
 
-use lambda_calculus::Term;
-use std::fs::OpenOptions;
-use std::io::{self, BufRead, BufReader, Write};
+use crate::config::ConfigSeed;
+use lambda_calculus::{parser::ParseError, Term};
+use log::warn;
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // This was shamelessly stolen from
 // https://play.rust-lang.org/?version=stable&mode=debug&edition=2015&gist=e241493d100ecaadac3c99f37d0f766f
@@ -112,38 +119,677 @@ where
 
 impl<U, T> Eq for HeapObject<U, T> where U: Ord {}
 
-/// Read lambda expressions from stdin and return an iterator over them
-pub fn read_inputs() -> impl Iterator<Item = Term> {
-    let mut expression_strings = Vec::<String>::new();
-    let stdin = io::stdin();
-    let reader = BufReader::new(stdin.lock());
+/// Return the `k` items with the largest `key`, largest first. Items with equal keys keep
+/// their input order.
+pub fn top_k_by<T, K, F>(items: impl IntoIterator<Item = T>, k: usize, key: F) -> Vec<T>
+where
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    let mut items = items.into_iter().collect::<Vec<T>>();
+    items.sort_by_cached_key(|x| Reverse(key(x)));
+    items.truncate(k);
+    items
+}
 
-    for line in reader.lines() {
-        match line {
-            Ok(line) => expression_strings.push(line),
-            Err(_) => break,
+/// Parse one classic-notation expression per item. Items that fail to parse are reported
+/// with their index and error rather than dropped silently.
+pub fn parse_inputs<S: AsRef<str>>(
+    inputs: impl IntoIterator<Item = S>,
+) -> (Vec<Term>, Vec<(usize, ParseError)>) {
+    let mut expressions = Vec::new();
+    let mut errors = Vec::new();
+    for (i, s) in inputs.into_iter().enumerate() {
+        match lambda_calculus::parse(s.as_ref(), lambda_calculus::Classic) {
+            Ok(expr) => expressions.push(expr),
+            Err(e) => errors.push((i, e)),
         }
     }
+    (expressions, errors)
+}
 
-    let expressions = expression_strings
+/// Like `parse_inputs`, but for the lines of an input file: whitespace is trimmed, and blank
+/// lines and lines starting with `#` are skipped. Errors are reported with the index of the
+/// line they occurred on.
+pub fn parse_input_lines<S: AsRef<str>>(
+    lines: impl IntoIterator<Item = S>,
+) -> (Vec<Term>, Vec<(usize, ParseError)>) {
+    let lines = lines.into_iter().collect::<Vec<_>>();
+    let kept = lines
         .iter()
-        .map(|s| lambda_calculus::parse(s, lambda_calculus::Classic).unwrap())
-        .collect::<Vec<Term>>();
+        .enumerate()
+        .map(|(i, line)| (i, line.as_ref().trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .collect::<Vec<_>>();
+
+    let (expressions, errors) = parse_inputs(kept.iter().map(|(_, line)| line));
+    let errors = errors.into_iter().map(|(j, e)| (kept[j].0, e)).collect();
+    (expressions, errors)
+}
+
+/// Read lambda expressions from stdin, one per line, and return an iterator over them. Blank
+/// lines and `#` comments are ignored, and lines that fail to parse are skipped, logging a
+/// message at `warn` level with their line number. Without an installed logger, as for
+/// library and Python users, skipped lines go unreported.
+///
+/// If stdin holds terms written by `write_terms_binary` instead, they are decoded without
+/// parsing.
+pub fn read_inputs() -> impl Iterator<Item = Term> {
+    let mut input = Vec::new();
+    if let Err(e) = io::stdin().lock().read_to_end(&mut input) {
+        warn!("Stopped reading stdin: {e}");
+    }
+    if input.starts_with(TERMS_MAGIC) {
+        let terms = decode_terms(&input).unwrap_or_else(|e| {
+            warn!("Skipping binary input: {e}");
+            Vec::new()
+        });
+        return terms.into_iter();
+    }
+
+    let input = String::from_utf8_lossy(&input);
+    let expression_strings = input.lines().collect::<Vec<_>>();
+    let (expressions, errors) = parse_input_lines(&expression_strings);
+    for (i, e) in errors {
+        warn!("Skipping line {}: {e} ({:?})", i + 1, expression_strings[i]);
+    }
     expressions.into_iter()
 }
 
-pub fn dump_series_to_file<T>(fname: &str, series: &[T], id: &[usize]) -> io::Result<()>
+/// The first bytes of a file written by `write_terms_binary`.
+const TERMS_MAGIC: &[u8] = b"ALCHEMY-TERMS\x01";
+
+const TAG_VAR: u8 = 0;
+const TAG_ABS: u8 = 1;
+const TAG_APP: u8 = 2;
+
+/// Write `terms` to `path` in a compact binary form that `read_terms_binary` loads without
+/// parsing: a header, the number of terms, then each term in prefix order, with one tag byte
+/// per node and De Bruijn indices as LEB128 varints. Free variables are kept as they are.
+pub fn write_terms_binary(path: impl AsRef<Path>, terms: &[Term]) -> io::Result<()> {
+    let mut bytes = TERMS_MAGIC.to_vec();
+    write_varint(&mut bytes, terms.len());
+    for term in terms {
+        encode_term(&mut bytes, term);
+    }
+    fs::write(path, bytes)
+}
+
+/// Read terms written by `write_terms_binary`.
+pub fn read_terms_binary(path: impl AsRef<Path>) -> io::Result<Vec<Term>> {
+    decode_terms(&fs::read(path)?)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        bytes.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+}
+
+fn encode_term(bytes: &mut Vec<u8>, term: &Term) {
+    match term {
+        Term::Var(i) => {
+            bytes.push(TAG_VAR);
+            write_varint(bytes, *i);
+        }
+        Term::Abs(body) => {
+            bytes.push(TAG_ABS);
+            encode_term(bytes, body);
+        }
+        Term::App(app) => {
+            bytes.push(TAG_APP);
+            encode_term(bytes, &app.0);
+            encode_term(bytes, &app.1);
+        }
+    }
+}
+
+fn invalid_terms(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("bad term file: {msg}"))
+}
+
+fn decode_terms(bytes: &[u8]) -> io::Result<Vec<Term>> {
+    let mut rest = bytes
+        .strip_prefix(TERMS_MAGIC)
+        .ok_or_else(|| invalid_terms("missing header"))?;
+    let n = read_varint(&mut rest)?;
+    // Every term takes at least two bytes, so don't trust `n` further than that
+    let mut terms = Vec::with_capacity(n.min(rest.len() / 2));
+    for _ in 0..n {
+        terms.push(decode_term(&mut rest)?);
+    }
+    if !rest.is_empty() {
+        return Err(invalid_terms("trailing bytes"));
+    }
+    Ok(terms)
+}
+
+fn read_byte(rest: &mut &[u8]) -> io::Result<u8> {
+    let (&byte, tail) = rest
+        .split_first()
+        .ok_or_else(|| invalid_terms("truncated"))?;
+    *rest = tail;
+    Ok(byte)
+}
+
+fn read_varint(rest: &mut &[u8]) -> io::Result<usize> {
+    let mut n = 0usize;
+    for shift in (0..usize::BITS).step_by(7) {
+        let byte = read_byte(rest)?;
+        n |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(invalid_terms("index too large"))
+}
+
+fn decode_term(rest: &mut &[u8]) -> io::Result<Term> {
+    match read_byte(rest)? {
+        TAG_VAR => Ok(Term::Var(read_varint(rest)?)),
+        TAG_ABS => Ok(Term::Abs(Box::new(decode_term(rest)?))),
+        TAG_APP => {
+            let lhs = decode_term(rest)?;
+            let rhs = decode_term(rest)?;
+            Ok(Term::App(Box::new((lhs, rhs))))
+        }
+        _ => Err(invalid_terms("unknown tag")),
+    }
+}
+
+/// Where series files go: a directory, and a template for paths within it. The template may
+/// contain `{fname}`, `{id}` and `{timestamp}`, and may name subdirectories. `{id}` is the
+/// series id joined with `-`, and `{timestamp}` is the Unix time in seconds at which the
+/// `SeriesOutput` was made, so every file of one run shares it.
+#[derive(Debug, Clone)]
+pub struct SeriesOutput {
+    dir: PathBuf,
+    template: String,
+    timestamp: u64,
+}
+
+impl SeriesOutput {
+    pub fn new(dir: impl Into<PathBuf>, template: &str) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_secs());
+        SeriesOutput {
+            dir: dir.into(),
+            template: template.to_string(),
+            timestamp,
+        }
+    }
+
+    /// The path of series `fname` with `id`. Directories on the way are created if missing.
+    pub fn path(&self, fname: &str, id: &[usize]) -> io::Result<PathBuf> {
+        let id = id
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("-");
+        let name = self
+            .template
+            .replace("{fname}", fname)
+            .replace("{id}", &id)
+            .replace("{timestamp}", &self.timestamp.to_string());
+        let path = self.dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(path)
+    }
+}
+
+/// Series files named `{fname}.txt` in the working directory.
+impl Default for SeriesOutput {
+    fn default() -> Self {
+        SeriesOutput::new(".", "{fname}.txt")
+    }
+}
+
+/// Append `series` as a row, labelled with `id`, to its file under `output`.
+pub fn dump_series_to_file<T>(
+    output: &SeriesOutput,
+    fname: &str,
+    series: &[T],
+    id: &[usize],
+) -> io::Result<()>
 where
     T: fmt::Debug,
 {
-    let mut file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(format!("{fname}.txt"))?;
-    write!(file, "{id:?}; ")?;
+    let mut file = open_series_file(&output.path(fname, id)?)?;
+    file.write_all(format_series_row(series, id).as_bytes())
+}
+
+fn open_series_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new().append(true).create(true).open(path)
+}
+
+fn format_series_row<T: fmt::Debug>(series: &[T], id: &[usize]) -> String {
+    let mut row = format!("{id:?}; ");
     for i in series {
-        write!(file, "{:?}; ", i)?;
+        row.push_str(&format!("{:?}; ", i));
+    }
+    row.push('\n');
+    row
+}
+
+/// Appends series to their file under a `SeriesOutput` in the same format as
+/// `dump_series_to_file`, but keeps the file open between rows. Each row is written whole under
+/// a lock, so one writer can be shared between tasks and rows never interleave.
+pub struct SeriesWriter {
+    file: Mutex<File>,
+}
+
+impl SeriesWriter {
+    /// Open series `fname` under `output`. One file holds the rows of every id, so `{id}` in
+    /// the template is left empty.
+    pub fn new(output: &SeriesOutput, fname: &str) -> io::Result<Self> {
+        Ok(SeriesWriter {
+            file: Mutex::new(open_series_file(&output.path(fname, &[])?)?),
+        })
+    }
+
+    pub fn append<T: fmt::Debug>(&self, id: &[usize], series: &[T]) -> io::Result<()> {
+        let row = format_series_row(series, id);
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(row.as_bytes())
+    }
+}
+
+/// Declarative description of an initial population. Entries are either an exact count of a
+/// term, or a weight; weighted entries share whatever part of `total` the counted entries leave.
+#[derive(Debug, Clone, Default)]
+pub struct SampleSpec {
+    counts: Vec<(Term, usize)>,
+    weights: Vec<(Term, f64)>,
+    total: usize,
+    seed: Option<[u8; 32]>,
+}
+
+impl SampleSpec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Include exactly `n` copies of `term`.
+    pub fn count(mut self, term: Term, n: usize) -> Self {
+        self.counts.push((term, n));
+        self
+    }
+
+    /// Include `term` in proportion to `weight` among the weighted entries.
+    pub fn weight(mut self, term: Term, weight: f64) -> Self {
+        self.weights.push((term, weight));
+        self
+    }
+
+    /// Set the total sample size. Only affects weighted entries.
+    pub fn total(mut self, total: usize) -> Self {
+        self.total = total;
+        self
+    }
+
+    /// Shuffle the built sample with the given seed.
+    pub fn shuffled(mut self, seed: [u8; 32]) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Number of copies of each weighted entry. Uses largest remainders so the counts sum to
+    /// exactly the space left in `total`.
+    fn weighted_counts(&self) -> Vec<usize> {
+        let counted: usize = self.counts.iter().map(|(_, n)| n).sum();
+        let remaining = self.total.saturating_sub(counted);
+        let weight_sum: f64 = self.weights.iter().map(|(_, w)| w).sum();
+        if remaining == 0 || weight_sum <= 0.0 {
+            return vec![0; self.weights.len()];
+        }
+
+        let exact = self
+            .weights
+            .iter()
+            .map(|(_, w)| w / weight_sum * remaining as f64)
+            .collect::<Vec<f64>>();
+        let mut counts = exact
+            .iter()
+            .map(|x| x.floor() as usize)
+            .collect::<Vec<usize>>();
+        let mut by_remainder = (0..exact.len()).collect::<Vec<usize>>();
+        by_remainder.sort_by(|&a, &b| {
+            (exact[b] - exact[b].floor()).total_cmp(&(exact[a] - exact[a].floor()))
+        });
+        let short = remaining - counts.iter().sum::<usize>();
+        for &i in by_remainder.iter().take(short) {
+            counts[i] += 1;
+        }
+        counts
+    }
+
+    pub fn build(&self) -> Vec<Term> {
+        let weighted = self
+            .weights
+            .iter()
+            .zip(self.weighted_counts())
+            .map(|((term, _), n)| (term, n));
+        let mut sample = Vec::new();
+        for (term, n) in self.counts.iter().map(|(t, n)| (t, *n)).chain(weighted) {
+            sample.extend(std::iter::repeat_n(term, n).cloned());
+        }
+        if let Some(seed) = self.seed {
+            sample.shuffle(&mut ChaCha8Rng::from_seed(seed));
+        }
+        sample
+    }
+}
+
+/// Run `run` once for each of `n_seeds` seeds derived from `base` with `ConfigSeed::derive`,
+/// returning each seed with its result. The seeds only depend on `base`, so a sweep can be
+/// repeated or extended exactly.
+pub fn seed_sweep<R>(
+    base: ConfigSeed,
+    n_seeds: usize,
+    mut run: impl FnMut(ConfigSeed) -> R,
+) -> Vec<(ConfigSeed, R)> {
+    (0..n_seeds as u64)
+        .map(|i| {
+            let seed = base.derive(i);
+            (seed, run(seed))
+        })
+        .collect()
+}
+
+/// Summary statistics of a set of numeric results, e.g. from `seed_sweep`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    /// Number of values
+    pub n: usize,
+
+    /// Arithmetic mean
+    pub mean: f64,
+
+    /// Sample standard deviation, or `0.0` for a single value
+    pub std: f64,
+
+    sorted: Vec<f64>,
+}
+
+impl Summary {
+    /// Summarize `values`, or return `None` if there are none. NaNs sort last.
+    pub fn of(values: impl IntoIterator<Item = f64>) -> Option<Summary> {
+        let mut sorted = values.into_iter().collect::<Vec<_>>();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(f64::total_cmp);
+
+        Some(Summary {
+            n: sorted.len(),
+            mean: stats::mean(&sorted)?,
+            std: stats::variance(&sorted)?.sqrt(),
+            sorted,
+        })
+    }
+
+    /// The `q`-quantile for `q` in `[0, 1]`, as `stats::quantile`.
+    pub fn quantile(&self, q: f64) -> f64 {
+        stats::quantile(&self.sorted, q).unwrap()
+    }
+
+    pub fn min(&self) -> f64 {
+        self.sorted[0]
+    }
+
+    pub fn median(&self) -> f64 {
+        self.quantile(0.5)
+    }
+
+    pub fn max(&self) -> f64 {
+        self.sorted[self.n - 1]
+    }
+}
+
+/// Per-timepoint `(mean, variance)` across an ensemble of runs, each of which polled the same
+/// scalar at the same times, e.g. the rows of `entropy::entropy_time_series`. Runs may be
+/// ragged, as when a killer-poller stopped them early: the statistics at each index are taken
+/// over the runs that reached it, so the result is as long as the longest run. Variances are
+/// sample variances, `0.0` where only one run has data.
+pub fn ensemble_stats(series: &[Vec<f32>]) -> Vec<(f32, f32)> {
+    let len = series.iter().map(Vec::len).max().unwrap_or(0);
+    (0..len)
+        .map(|t| {
+            let values = series.iter().filter_map(|s| s.get(t).copied());
+            let values = values.collect::<Vec<_>>();
+            let mean = stats::mean(&values).unwrap();
+            let variance = stats::variance(&values).unwrap();
+            (mean as f32, variance as f32)
+        })
+        .collect()
+}
+
+/// Moments, quantiles and histograms of numeric series, such as the reduction counts of
+/// reactions. Every function takes a slice of `usize`, `f32` or `f64` and computes in `f64`.
+pub mod stats {
+    /// A number that `stats` can summarize.
+    pub trait Value: Copy {
+        fn to_f64(self) -> f64;
+    }
+
+    impl Value for usize {
+        fn to_f64(self) -> f64 {
+            self as f64
+        }
+    }
+
+    impl Value for f32 {
+        fn to_f64(self) -> f64 {
+            self as f64
+        }
+    }
+
+    impl Value for f64 {
+        fn to_f64(self) -> f64 {
+            self
+        }
+    }
+
+    /// Arithmetic mean of `values`, or `None` if there are none.
+    pub fn mean<T: Value>(values: &[T]) -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+        Some(values.iter().map(|x| x.to_f64()).sum::<f64>() / values.len() as f64)
+    }
+
+    /// Sample variance of `values`, `0.0` for a single value, or `None` if there are none.
+    pub fn variance<T: Value>(values: &[T]) -> Option<f64> {
+        let mean = mean(values)?;
+        let n = values.len();
+        if n == 1 {
+            return Some(0.0);
+        }
+        let squares = values.iter().map(|x| (x.to_f64() - mean).powi(2));
+        Some(squares.sum::<f64>() / (n - 1) as f64)
+    }
+
+    /// The `q`-quantile of the ascending `sorted` for `q` in `[0, 1]`, interpolating linearly
+    /// between the closest values, or `None` if there are none. `q` is clamped to `[0, 1]`.
+    pub fn quantile<T: Value>(sorted: &[T], q: f64) -> Option<f64> {
+        if sorted.is_empty() {
+            return None;
+        }
+        let pos = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+        let (lo, hi) = (sorted[pos.floor() as usize], sorted[pos.ceil() as usize]);
+        Some(lo.to_f64() + pos.fract() * (hi.to_f64() - lo.to_f64()))
+    }
+
+    /// Counts of values in equal-width bins spanning the range of a series.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Histogram {
+        /// Lower edge of the first bin, the smallest value
+        pub lo: f64,
+
+        /// Width of every bin. `0.0` if all values are equal.
+        pub width: f64,
+
+        /// Number of values in each bin. The largest value falls in the last bin.
+        pub counts: Vec<usize>,
+    }
+
+    impl Histogram {
+        /// Lower edge of bin `i`.
+        pub fn edge(&self, i: usize) -> f64 {
+            self.lo + i as f64 * self.width
+        }
+    }
+
+    /// Bin `values` into `n_bins` equal-width bins from their smallest to their largest value.
+    /// Returns `None` if there are no values or no bins. If all values are equal, they all
+    /// fall in the first bin.
+    pub fn histogram<T: Value>(values: &[T], n_bins: usize) -> Option<Histogram> {
+        if n_bins == 0 {
+            return None;
+        }
+        let values = values.iter().map(|x| x.to_f64());
+        let lo = values.clone().reduce(f64::min)?;
+        let hi = values.clone().reduce(f64::max)?;
+
+        let width = (hi - lo) / n_bins as f64;
+        let mut counts = vec![0; n_bins];
+        for x in values {
+            let bin = if width > 0.0 {
+                ((x - lo) / width) as usize
+            } else {
+                0
+            };
+            counts[bin.min(n_bins - 1)] += 1;
+        }
+        Some(Histogram { lo, width, counts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{
+        combinators::{I, K, S},
+        Term,
+    };
+
+    use crate::config::ConfigSeed;
+
+    use super::{
+        dump_series_to_file, ensemble_stats, parse_input_lines, parse_inputs, read_terms_binary,
+        seed_sweep, stats, write_terms_binary, SampleSpec, SeriesOutput, SeriesWriter, Summary,
+    };
+
+    #[test]
+    fn weighted_sample_fills_total() {
+        let sample = SampleSpec::new()
+            .count(I(), 1)
+            .weight(S(), 1.0)
+            .weight(K(), 2.0)
+            .total(11)
+            .build();
+        assert_eq!(sample.len(), 11);
+        assert_eq!(sample.iter().filter(|t| **t == S()).count(), 3);
+        assert_eq!(sample.iter().filter(|t| **t == K()).count(), 7);
+    }
+
+    #[test]
+    fn input_lines_skip_comments_and_blanks() {
+        let lines = ["# combinators", r"  \x.x  ", "", r"\x.(", r"# \x.\y.x"];
+        let (terms, errors) = parse_input_lines(lines);
+        assert_eq!(terms, vec![I()]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 3);
+    }
+
+    #[test]
+    fn sweep_summary() {
+        let base = ConfigSeed::new([3; 32]);
+        let runs = seed_sweep(base, 4, |seed| seed.seed().unwrap()[0] as f64);
+        assert_eq!(runs.len(), 4);
+        assert_eq!(runs[2].0.seed(), base.derive(2).seed());
+        assert_eq!(runs[2].1, base.derive(2).seed().unwrap()[0] as f64);
+
+        let summary = Summary::of([1.0, 2.0, 3.0, 4.0]).unwrap();
+        assert_eq!(summary.mean, 2.5);
+        assert_eq!(summary.median(), 2.5);
+        assert_eq!(summary.quantile(1.0 / 3.0), 2.0);
+        assert!((summary.std - 1.290_994).abs() < 1e-6);
+        assert!(Summary::of([]).is_none());
+    }
+
+    #[test]
+    fn reduction_stats() {
+        let reductions: [usize; 5] = [1, 2, 4, 8, 10];
+        assert_eq!(stats::mean(&reductions), Some(5.0));
+        assert_eq!(stats::variance(&reductions), Some(15.0));
+        assert_eq!(stats::quantile(&reductions, 0.5), Some(4.0));
+        assert_eq!(stats::quantile(&reductions, 0.95), Some(9.6));
+        assert_eq!(stats::mean::<f32>(&[]), None);
+
+        let hist = stats::histogram(&reductions, 3).unwrap();
+        assert_eq!(hist.counts, vec![2, 1, 2]);
+        assert_eq!(hist.edge(1), 4.0);
+        assert_eq!(
+            stats::histogram(&[2.0f32; 4], 3).unwrap().counts,
+            vec![4, 0, 0]
+        );
+    }
+
+    #[test]
+    fn ensemble_stats_handle_ragged_runs() {
+        let runs = vec![vec![1.0, 2.0, 6.0], vec![3.0, 4.0], vec![]];
+        assert_eq!(ensemble_stats(&runs), [(2.0, 2.0), (3.0, 2.0), (6.0, 0.0)]);
+        assert!(ensemble_stats(&[]).is_empty());
+    }
+
+    #[test]
+    fn binary_terms_round_trip() {
+        let lines = ["\\x.x", "\\x.\\y.x y (\\z.z)", "a (\\x.b x)"];
+        let (mut terms, errors) = parse_inputs(lines);
+        assert!(errors.is_empty());
+        // A variable index that needs more than one byte
+        terms.push(Term::Var(300));
+
+        let path = std::env::temp_dir().join(format!("terms-{}.bin", std::process::id()));
+        write_terms_binary(&path, &terms).unwrap();
+        let read = read_terms_binary(&path);
+        std::fs::write(&path, b"\\x.x").unwrap();
+        assert!(read_terms_binary(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap(), terms);
+    }
+
+    #[test]
+    fn series_paths_follow_template() {
+        let dir = std::env::temp_dir().join(format!("series-{}", std::process::id()));
+        let output = SeriesOutput::new(&dir, "run/{fname}-{id}.txt");
+        assert_eq!(
+            output.path("counts", &[3, 1]).unwrap(),
+            dir.join("run/counts-3-1.txt")
+        );
+
+        dump_series_to_file(&output, "counts", &[1, 2], &[3, 1]).unwrap();
+        dump_series_to_file(&output, "counts", &[4], &[3, 1]).unwrap();
+        let rows = std::fs::read_to_string(dir.join("run/counts-3-1.txt")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rows, "[3, 1]; 1; 2; \n[3, 1]; 4; \n");
+    }
+
+    #[test]
+    fn writers_follow_output_dir() {
+        let dir = std::env::temp_dir().join(format!("writer-{}", std::process::id()));
+        let output = SeriesOutput::new(&dir, "run/{fname}.txt");
+        let writer = SeriesWriter::new(&output, "counts").unwrap();
+        writer.append(&[0], &[1, 2]).unwrap();
+        writer.append(&[1], &[3]).unwrap();
+        drop(writer);
+        let rows = std::fs::read_to_string(dir.join("run/counts.txt")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rows, "[0]; 1; 2; \n[1]; 3; \n");
     }
-    writeln!(file)?;
-    Ok(())
 }
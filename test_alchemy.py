@@ -29,7 +29,12 @@ def main():
         assert bytes(hx) == raw, f"decode_hex_py mismatch: {hx}"
         re_hx = alchemy.encode_hex_py(list(raw))
         assert re_hx.lower() == "0001abcd", f"encode_hex_py mismatch: {re_hx}"
-        print("✅ Utilities OK (decode_hex_py / encode_hex_py)")
+        closed = alchemy.close_term("x y")
+        assert alchemy.close_term(closed) == closed, "closing a closed term should not change it"
+        assert alchemy.pretty("\\a.\\b.a b") == "\\x.\\y.x y"
+        assert alchemy.arity("\\a.\\b.a b") == 2
+        assert alchemy.arity("\\a.a (\\b.b)") == 1
+        print("✅ Utilities OK (decode_hex_py / encode_hex_py / close_term / pretty / arity)")
     except Exception:
         die("❌ Utilities failed\n" + traceback.format_exc())
 
@@ -40,7 +45,12 @@ def main():
         std_none    = alchemy.PyStandardization("none")
         for s in (std_prefix, std_postfix, std_none):
             assert s is not None
-        print("✅ PyStandardization constructed (prefix/postfix/none)")
+        assert alchemy.PyStandardization.values() == ["prefix", "postfix", "none"]
+        assert repr(std_prefix) == "Standardization('prefix')"
+        assert std_prefix == alchemy.PyStandardization("prefix")
+        assert std_prefix != std_postfix
+        assert len({std_none, alchemy.PyStandardization("none")}) == 1
+        print("✅ PyStandardization constructed (prefix/postfix/none, repr/eq/values)")
     except Exception:
         die("❌ PyStandardization construction failed\n" + traceback.format_exc())
 
@@ -56,7 +66,25 @@ def main():
         many = bt.generate_n(5)
         assert isinstance(one, str) and len(one) > 0, "BTreeGen.generate must return a non-empty string"
         assert isinstance(many, list) and len(many) == 5 and all(isinstance(x, str) and x for x in many)
-        print("✅ PyBTreeGen.generate / generate_n OK")
+        shallow = alchemy.PyBTreeGen.from_config(
+            size=6,
+            freevar_generation_probability=0.3,
+            max_free_vars=3,
+            std=std_prefix,
+            max_depth=8,
+        )
+        assert len(shallow.generate_n(5)) == 5
+        try:
+            alchemy.PyBTreeGen.from_config(
+                size=0,
+                freevar_generation_probability=0.3,
+                max_free_vars=3,
+                std=std_prefix,
+            )
+            die("❌ A zero btree size should raise ValueError")
+        except ValueError:
+            pass
+        print("✅ PyBTreeGen.generate / generate_n / max_depth / size check OK")
     except Exception:
         die("❌ PyBTreeGen tests failed\n" + traceback.format_exc())
 
@@ -64,6 +92,8 @@ def main():
     try:
         soup1 = alchemy.PySoup()
         assert soup1.len() == 0, "New soup should be empty"
+        assert soup1.is_dead(), "An empty soup cannot react"
+        assert soup1.simulate_for(5, False) == 0
 
         reactor = alchemy.PyReactor()
         soup2 = alchemy.PySoup.from_config(reactor)
@@ -80,6 +110,10 @@ def main():
         uniq = soup2.unique_expressions()
         counts = soup2.expression_counts()
         ent = soup2.population_entropy()
+        assert abs(soup2.population_entropy_base(10.0) - ent) < 1e-5
+        assert soup2.population_entropy_base() >= ent
+        assert 0.0 <= soup2.normalized_entropy() <= 1.0
+        assert 0.0 <= soup2.simpson_diversity() < 1.0
         col = soup2.collisions()
         ln = soup2.len()
 
@@ -89,6 +123,8 @@ def main():
             isinstance(p, (tuple, list)) and len(p) == 2 and isinstance(p[0], str) and isinstance(p[1], int)
             for p in counts
         )
+        assert sum(c for _, c in counts) == soup2.len()
+        assert sorted(c for _, c in soup2.expression_counts(pretty=False)) == sorted(c for _, c in counts)
         assert isinstance(ent, float)
         assert isinstance(col, int)
         assert isinstance(ln, int)
@@ -110,10 +146,145 @@ def main():
         assert isinstance(term, str) and term, "FontanaGen.generate must return a non-empty string"
         terms = fg.generate_n(5)
         assert isinstance(terms, list) and len(terms) == 5 and all(isinstance(t, str) and t for t in terms)
+        # New keyword arguments go last, so existing positional calls keep their meaning
+        params = list(inspect.signature(alchemy.PyFontanaGen.from_config).parameters)
+        assert params[-3:] == ["flatten_sizes", "target_per_size", "close_terms"], params
         print("✅ PyFontanaGen.from_config / generate / generate_n OK")
     except Exception:
         die("❌ PyFontanaGen tests failed\n" + traceback.format_exc())
 
+    # ---------- simulate_for progress / cancellation ----------
+    try:
+        calls = []
+
+        def progress(done, successes):
+            calls.append((done, successes))
+            return len(calls) < 2  # cancel after the second report
+
+        soup3 = alchemy.PySoup()
+        soup3.perturb(bt.generate_n(50))
+        successes = soup3.simulate_for(100, False, progress=progress, progress_interval=10)
+        assert [done for done, _ in calls] == [10, 20], f"unexpected progress reports: {calls}"
+        assert successes == calls[-1][1]
+        print("✅ PySoup.simulate_for progress callback / cancellation OK")
+    except Exception:
+        die("❌ simulate_for progress tests failed\n" + traceback.format_exc())
+
+    # ---------- Generator notation ----------
+    try:
+        seed = "00" * 32
+        classic = alchemy.PyBTreeGen.from_config(
+            size=6, freevar_generation_probability=0.3, max_free_vars=3, std=std_prefix, seed=seed,
+        ).generate_n(5)
+        debruijn = alchemy.PyBTreeGen.from_config(
+            size=6, freevar_generation_probability=0.3, max_free_vars=3, std=std_prefix, seed=seed,
+            notation="debruijn",
+        ).generate_n(5)
+        assert len(debruijn) == 5 and all(isinstance(t, str) and t for t in debruijn)
+        assert classic != debruijn, "De Bruijn output should differ from classic output"
+        for c, d in zip(classic, debruijn):
+            assert alchemy.pretty(c) == alchemy.pretty(d, notation="debruijn"), f"{c!r} vs {d!r}"
+        fontana = {
+            notation: alchemy.PyFontanaGen.from_config(
+                abs_range=(0.2, 0.6), app_range=(0.2, 0.6), min_depth=1, max_depth=5,
+                free_variable_probability=0.25, max_free_vars=3, seed=seed, notation=notation,
+            ).generate_n(5)
+            for notation in ("classic", "debruijn")
+        }
+        for c, d in zip(fontana["classic"], fontana["debruijn"]):
+            assert alchemy.pretty(c) == alchemy.pretty(d, notation="debruijn"), f"{c!r} vs {d!r}"
+        try:
+            alchemy.PyBTreeGen(notation="polish")
+            die("❌ Unknown notation should raise ValueError")
+        except ValueError:
+            pass
+        print("✅ Generator notation toggle / round trip OK (classic / debruijn)")
+    except Exception:
+        die("❌ Generator notation tests failed\n" + traceback.format_exc())
+
+    # ---------- Reactor timeout ----------
+    try:
+        reactor = alchemy.PyReactor()
+        assert reactor.reduction_timeout_ms is None, "timeout should default to None"
+        reactor.reduction_timeout_ms = 50
+        assert reactor.reduction_timeout_ms == 50
+        assert reactor.depth_cutoff is None, "depth cutoff should default to None"
+        reactor.depth_cutoff = 64
+        assert reactor.depth_cutoff == 64
+        assert reactor.escalation is None, "escalation should default to None"
+        reactor.escalation = (1000, 500)
+        assert reactor.escalation == (1000, 500)
+        soup5 = alchemy.PySoup.from_config(reactor)
+        soup5.perturb(bt.generate_n(10))
+        soup5.simulate_for(25, False)
+
+        # Omega loops at a constant size, so only the clock can stop it
+        diverging = alchemy.PyReactor()
+        diverging.reduction_cutoff = 10**9
+        diverging.size_cutoff = 10**6
+        diverging.reduction_timeout_ms = 1
+        omega = "\\x.x x"
+        err = alchemy.PySoup.from_config(diverging).react_pair(omega, omega)
+        assert isinstance(err, alchemy.PyReactionError), f"expected a reaction error, got {err!r}"
+        assert err.kind == "timeout", f"unexpected error kind: {err.kind}"
+        assert "time limit" in str(err), f"unexpected error message: {err}"
+        products = alchemy.PySoup.from_config(alchemy.PyReactor()).react_pair("\\x.\\y.x", omega)
+        assert [alchemy.pretty(p) for p in products] == [alchemy.pretty("\\y.\\x.x x")], products
+        print("✅ PyReactor.reduction_timeout_ms / depth_cutoff / escalation / timeout errors OK")
+    except Exception:
+        die("❌ Reactor timeout tests failed\n" + traceback.format_exc())
+
+    # ---------- from_terms ----------
+    try:
+        soup6 = alchemy.PySoup.from_terms(alchemy.PyReactor(), bt.generate_n(7))
+        assert soup6.len() == 7, f"expected 7 expressions, got {soup6.len()}"
+        try:
+            alchemy.PySoup.from_terms(alchemy.PyReactor(), ["\\x.(x"])
+            die("❌ from_terms should reject malformed expressions")
+        except ValueError:
+            pass
+        print("✅ PySoup.from_terms OK")
+    except Exception:
+        die("❌ from_terms tests failed\n" + traceback.format_exc())
+
+    # ---------- simulate_and_record ----------
+    try:
+        soup7 = alchemy.PySoup.from_terms(alchemy.PyReactor(), bt.generate_n(20))
+        tape = soup7.simulate_and_record(50, 10)
+        assert tape.polling_interval() == 10
+        series = tape.to_series(lambda s: s.len())
+        assert [i for i, _ in series] == list(range(5)), f"unexpected indices: {series}"
+        assert tape.final_state().collisions() == soup7.collisions()
+        print("✅ PySoup.simulate_and_record / PyTape OK")
+    except Exception:
+        die("❌ PyTape tests failed\n" + traceback.format_exc())
+
+    # ---------- perturb_checked ----------
+    try:
+        soup4 = alchemy.PySoup()
+        errors = soup4.perturb_checked(["\\x.x", "\\x.(x", "\\x.\\y.x"])
+        assert soup4.len() == 2, f"expected the 2 valid expressions to be added, got {soup4.len()}"
+        assert [i for i, _ in errors] == [1], f"unexpected parse errors: {errors}"
+        assert all(isinstance(e, str) and e for _, e in errors)
+        print("✅ PySoup.perturb_checked reports parse errors OK")
+    except Exception:
+        die("❌ perturb_checked tests failed\n" + traceback.format_exc())
+
+    # ---------- presets ----------
+    try:
+        names = alchemy.preset_names()
+        assert "standard" in names, f"unexpected preset names: {names}"
+        for name in names:
+            alchemy.PySoup.from_terms(alchemy.PyReactor.preset(name, "00" * 32), ["\\x.x"])
+        try:
+            alchemy.PyReactor.preset("no_such_preset")
+            die("❌ unknown preset name should raise ValueError")
+        except ValueError:
+            pass
+        print("✅ PyReactor.preset / preset_names OK")
+    except Exception:
+        die("❌ preset tests failed\n" + traceback.format_exc())
+
     print("\n🎉 All python.rs bindings exercised successfully.")
 
 